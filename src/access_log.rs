@@ -0,0 +1,156 @@
+use std::{
+    convert::Infallible,
+    fs::OpenOptions,
+    io::{self, Write},
+    pin::Pin,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use bytes::Bytes;
+use http::Method;
+use http_body_util::combinators::BoxBody;
+use hyper::{
+    body::{Body, Frame, SizeHint},
+    Response,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessLogFormat {
+    Off,
+    Text,
+    Json,
+}
+
+impl FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(AccessLogFormat::Off),
+            "text" => Ok(AccessLogFormat::Text),
+            "json" => Ok(AccessLogFormat::Json),
+            _ => Err(format!("unknown access log format: {}", s)),
+        }
+    }
+}
+
+pub struct AccessLog {
+    format: AccessLogFormat,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+// The request-side fields of an access log line, captured before the
+// request is handed to a handler.
+pub struct AccessEntry {
+    pub method: Method,
+    pub path: String,
+    pub client_ip: Option<String>,
+    pub start: Instant,
+}
+
+impl AccessLog {
+    // Create a log writing to the given file, or to stdout if no path is
+    // given. Returns None if logging is disabled.
+    pub fn new(format: AccessLogFormat, path: Option<&str>) -> io::Result<Option<Arc<Self>>> {
+        if format == AccessLogFormat::Off {
+            return Ok(None);
+        }
+        let output: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+            None => Box::new(io::stdout()),
+        };
+        Ok(Some(Arc::new(AccessLog {
+            format,
+            output: Mutex::new(output),
+        })))
+    }
+
+    // Wrap a response so that a log line is written once its body has been
+    // fully sent (or dropped), so streamed responses report their full size
+    // and duration.
+    pub fn wrap(
+        self: &Arc<Self>,
+        entry: AccessEntry,
+        response: Response<BoxBody<Bytes, Infallible>>,
+    ) -> Response<BoxBody<Bytes, Infallible>> {
+        let status = response.status().as_u16();
+        response.map(|inner| {
+            BoxBody::new(LoggedBody {
+                inner,
+                bytes: 0,
+                status,
+                entry: Some(entry),
+                log: self.clone(),
+            })
+        })
+    }
+
+    pub fn write(&self, entry: &AccessEntry, status: u16, bytes: u64) {
+        let latency_ms = entry.start.elapsed().as_secs_f64() * 1000.0;
+        let client_ip = entry.client_ip.as_deref().unwrap_or("-");
+        let line = match self.format {
+            AccessLogFormat::Json => serde_json::json!({
+                "method": entry.method.as_str(),
+                "path": entry.path,
+                "client_ip": client_ip,
+                "status": status,
+                "bytes": bytes,
+                "latency_ms": latency_ms,
+            })
+            .to_string(),
+            _ => format!(
+                "method={} path={:?} client_ip={} status={} bytes={} latency_ms={:.1}",
+                entry.method, entry.path, client_ip, status, bytes, latency_ms
+            ),
+        };
+        let mut output = self.output.lock().unwrap();
+        if let Err(e) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            println!("Error writing access log: {}", e);
+        }
+    }
+}
+
+struct LoggedBody {
+    inner: BoxBody<Bytes, Infallible>,
+    bytes: u64,
+    status: u16,
+    entry: Option<AccessEntry>,
+    log: Arc<AccessLog>,
+}
+
+impl Body for LoggedBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        let result = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &result {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for LoggedBody {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            self.log.write(&entry, self.status, self.bytes);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Convert a UNIX timestamp (in seconds) to a UTC (year, month, day) and the
+// number of seconds into that day.
+//
+// This is the inverse of the days-from-civil algorithm described at
+// http://howardhinnant.github.io/date_algorithms.html.
+pub fn civil_from_timestamp(timestamp: f64) -> (i64, u32, u32, u32) {
+    let secs = timestamp.floor() as i64;
+    let days = secs.div_euclid(86400);
+    let secs_of_day = secs.rem_euclid(86400) as u32;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day, secs_of_day)
+}
+
+pub fn format_rfc3339(timestamp: f64) -> String {
+    let (year, month, day, secs) = civil_from_timestamp(timestamp);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+// The UTC (year, month) containing the timestamp.
+pub fn month_of(timestamp: f64) -> (i64, u32) {
+    let (year, month, _, _) = civil_from_timestamp(timestamp);
+    (year, month)
+}
+
+pub fn previous_month((year, month): (i64, u32)) -> (i64, u32) {
+    if month == 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+pub fn format_month((year, month): (i64, u32)) -> String {
+    format!("{:04}-{:02}", year, month)
+}
@@ -1,44 +1,218 @@
+use std::collections::BTreeSet;
 use std::error::Error;
 use std::fmt::Display;
 use std::future::Future;
-use std::mem::take;
-use std::time::Duration;
+use std::mem::{replace, take};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use futures::{stream, StreamExt};
+use reqwest::redirect::Policy;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thirtyfour::extensions::cdp::ChromeDevTools;
 use thirtyfour::prelude::{By, DesiredCapabilities, WebDriver, WebDriverError, WebDriverResult};
 use thirtyfour::ChromiumLikeCapabilities;
-use tokio::time::sleep;
+use tokio::sync::mpsc::Sender;
+use tokio::time::{sleep, timeout_at, Instant};
+use url::Url;
 
-#[derive(Deserialize, Serialize, Debug)]
+use super::replay::{to_recorded, Interaction, Recorder, Replay};
+use super::review_cache::{CachedPage, PageCache};
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct GeoLocation {
     pub latitude: f64,
     pub longitude: f64,
     pub accuracy: f64,
+
+    // Optional fields passed through to Emulation.setGeolocationOverride.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+    #[serde(
+        default,
+        rename = "altitudeAccuracy",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub altitude_accuracy: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl GeoLocation {
+    // Great-circle distance in meters to the given coordinates.
+    pub fn distance_to(&self, latitude: f64, longitude: f64) -> f64 {
+        const EARTH_RADIUS_M: f64 = 6371008.8;
+        let (lat1, lat2) = (self.latitude.to_radians(), latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LocationInfo {
     pub name: String,
     pub url: String,
     pub extra: Vec<String>,
+
+    // The number of currency symbols Google shows, from 1 ("$") to 4
+    // ("$$$$"), and the place's primary category, e.g. "Italian restaurant".
+    #[serde(default)]
+    pub price_level: Option<u8>,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+impl LocationInfo {
+    // Parse the (latitude, longitude) of the place from its URL, if present.
+    //
+    // Place URLs typically encode the pin as "!3d<lat>!4d<lon>" in the data
+    // segment, and sometimes the viewport as "@<lat>,<lon>,<zoom>z".
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        place_url_coordinates(&self.url)
+    }
+
+    // Fill in the price level and category from the subtitle lines of a
+    // search result, which look like "Italian restaurant · $$". The category
+    // is listed before the address and hours, so take the first part that
+    // isn't a rating, price, or anything else with digits in it.
+    fn fill_details_from_extra(&mut self) {
+        let parts = self
+            .extra
+            .iter()
+            .flat_map(|x| x.split(['·', '⋅']))
+            .map(str::trim)
+            .filter(|x| !x.is_empty());
+        for part in parts {
+            if let Some(level) = parse_price_level(part) {
+                self.price_level.get_or_insert(level);
+            } else if self.category.is_none()
+                && !part.chars().any(|c| c.is_ascii_digit() || c == '★')
+                && !part.to_lowercase().contains("star")
+            {
+                self.category = Some(part.to_owned());
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Parse a price level label like "$$" (or "€€"), as opposed to a price range
+// like "$10–20", which doesn't map onto a level.
+fn parse_price_level(label: &str) -> Option<u8> {
+    let mut chars = label.chars();
+    let symbol = chars.next()?;
+    if !"$€£¥₩₹".contains(symbol) || !chars.all(|c| c == symbol) {
+        return None;
+    }
+    let count = label.chars().count();
+    if count <= 4 {
+        Some(count as u8)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Review {
+    pub review_id: String,
     pub timestamp: f64,
     pub author: String,
     pub content: String,
     pub rating: f64,
+
+    // Reviewer reputation, when Google shows it alongside the author.
+    #[serde(default)]
+    pub is_local_guide: bool,
+    #[serde(default)]
+    pub author_review_count: Option<u64>,
+
+    // The unparsed entry from Google's response, only kept for debugging.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+
+    // The URL of the place the review is for, when reviews from several
+    // places are mixed together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub place: Option<String>,
 }
 
 #[derive(Debug, Default)]
 struct ReviewResult {
     pub next_url: Option<String>,
     pub reviews: Vec<Review>,
+
+    // Reviews which were left out because they couldn't be parsed.
+    pub skipped: usize,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PlaceRating {
+    pub rating: Option<f64>,
+    pub review_count: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReviewListOptions {
+    // Use Google's own filter to only list reviews in this language.
+    pub language: Option<String>,
+
+    // Only reviews newer than this timestamp are needed. When set, reviews are
+    // sorted newest-first so that pagination can stop early.
+    pub since: Option<f64>,
+
+    // Fail before loading any reviews if the place panel reports more than
+    // this many reviews.
+    pub max_total_reviews: Option<u64>,
+
+    // Keep the raw JSON entry for up to this many reviews on the first page,
+    // to help track down parser bugs.
+    pub raw_samples: usize,
+
+    // Pick one of the orders in Google's sort menu before listing reviews.
+    // Otherwise, reviews come in Google's default order, except that they're
+    // sorted newest-first when since is set.
+    pub google_sort: Option<GoogleSort>,
+}
+
+// The review orders offered by Google's sort menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GoogleSort {
+    Relevant,
+    Newest,
+    Highest,
+    Lowest,
+}
+
+impl GoogleSort {
+    // The labels of the menu option in English.
+    fn labels(&self) -> &'static [&'static str] {
+        match self {
+            GoogleSort::Relevant => &["most relevant", "relevant"],
+            GoogleSort::Newest => &["newest"],
+            GoogleSort::Highest => &["highest rating", "highest"],
+            GoogleSort::Lowest => &["lowest rating", "lowest"],
+        }
+    }
+}
+
+impl FromStr for GoogleSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "relevant" => Ok(GoogleSort::Relevant),
+            "newest" => Ok(GoogleSort::Newest),
+            "highest" => Ok(GoogleSort::Highest),
+            "lowest" => Ok(GoogleSort::Lowest),
+            _ => Err(format!("unknown sort order: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SearchResult {
     Singular(LocationInfo),
     Multiple(Vec<LocationInfo>),
@@ -53,6 +227,46 @@ pub enum ScrapeError {
     TimeoutError(String, Option<Box<ScrapeError>>),
     JsonError(serde_json::Error),
     ReqwestError(reqwest::Error),
+    // The browser landed somewhere other than the requested page, such as a
+    // cookie consent or "unusual traffic" page.
+    UnexpectedRedirect(String),
+    // The place has more reviews (the first value) than the caller was
+    // willing to scrape (the second value).
+    TooManyReviews(u64, u64),
+    // Google asked for a sign-in before showing the reviews.
+    SignInRequired,
+    // Another error, annotated with the step of the scrape it happened in.
+    PhaseError(ScrapePhase, Box<ScrapeError>),
+}
+
+// The steps of a scrape which depend on finding something on the page.
+#[derive(Clone, Copy, Debug)]
+pub enum ScrapePhase {
+    SearchBox,
+    Results,
+    MoreButton,
+    Reviews,
+}
+
+impl ScrapePhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScrapePhase::SearchBox => "search_box",
+            ScrapePhase::Results => "results",
+            ScrapePhase::MoreButton => "more_button",
+            ScrapePhase::Reviews => "reviews",
+        }
+    }
+
+    // What the phase looks for on the page.
+    pub fn selector(&self) -> &'static str {
+        match self {
+            ScrapePhase::SearchBox => "input[name=\"q\"]",
+            ScrapePhase::Results => "[role=\"main\"], [role=\"feed\"]",
+            ScrapePhase::MoreButton => "button[jsaction$=\"reviewChart.moreReviews\"]",
+            ScrapePhase::Reviews => "listugcposts responses or [data-review-id]",
+        }
+    }
 }
 
 impl From<WebDriverError> for ScrapeError {
@@ -83,141 +297,1374 @@ impl Display for ScrapeError {
             ScrapeError::TimeoutError(e, None) => write!(f, "TimeoutError({})", e),
             ScrapeError::JsonError(e) => write!(f, "JsonError({})", e),
             ScrapeError::ReqwestError(e) => write!(f, "ReqwestError({})", e),
+            ScrapeError::UnexpectedRedirect(url) => write!(f, "UnexpectedRedirect({})", url),
+            ScrapeError::TooManyReviews(count, max) => {
+                write!(
+                    f,
+                    "TooManyReviews({} reviews, at most {} allowed)",
+                    count, max
+                )
+            }
+            ScrapeError::SignInRequired => write!(f, "SignInRequired"),
+            ScrapeError::PhaseError(phase, e) => write!(
+                f,
+                "PhaseError({}, {}, {})",
+                phase.name(),
+                phase.selector(),
+                e
+            ),
+        }
+    }
+}
+
+impl Error for ScrapeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScrapeError::PhaseError(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl ScrapeError {
+    pub fn timeout<S: Display>(msg: S, inner: Option<ScrapeError>) -> Self {
+        ScrapeError::TimeoutError(format!("{}", msg), inner.map(|x| Box::new(x)))
+    }
+
+    pub fn parse_error<S: Display>(msg: S) -> Self {
+        ScrapeError::ParseError(format!("{}", msg))
+    }
+
+    pub fn fatal_parse_error<S: Display>(msg: S) -> Self {
+        ScrapeError::FatalParseError(format!("{}", msg))
+    }
+
+    // Annotate the error with the phase it happened in. Errors which already
+    // explain what went wrong are left as they are.
+    pub fn in_phase(self, phase: ScrapePhase) -> Self {
+        match self {
+            ScrapeError::UnexpectedRedirect(_)
+            | ScrapeError::TooManyReviews(_, _)
+            | ScrapeError::SignInRequired
+            | ScrapeError::PhaseError(_, _) => self,
+            e => ScrapeError::PhaseError(phase, Box::new(e)),
         }
     }
-}
 
-impl Error for ScrapeError {}
+    // Check if Google refused to serve the page because of unusual traffic.
+    pub fn is_blocked(&self) -> bool {
+        match self.root() {
+            ScrapeError::UnexpectedRedirect(url) => {
+                Url::parse(url).is_ok_and(|x| x.path().starts_with("/sorry"))
+            }
+            _ => false,
+        }
+    }
+
+    // Get the underlying error, without any phase annotation.
+    pub fn root(&self) -> &ScrapeError {
+        match self {
+            ScrapeError::PhaseError(_, e) => e.root(),
+            e => e,
+        }
+    }
+}
+
+pub struct ReviewIter {
+    next_result: Option<ReviewResult>,
+    next_url: Option<String>,
+    page_delay: Duration,
+    stop_before: Option<f64>,
+    total_count: Option<u64>,
+    empty_pages: usize,
+    deadline: Option<Instant>,
+    hit_deadline: bool,
+    page_cache: Option<Arc<PageCache>>,
+    page_source: PageSource,
+    skipped_reviews: usize,
+}
+
+// The most pages in a row which may contain no reviews before pagination is
+// abandoned, in case Google keeps handing out tokens for empty pages.
+const MAX_CONSECUTIVE_EMPTY_PAGES: usize = 5;
+
+impl<'a> ReviewIter {
+    fn new(first: ReviewResult) -> Self {
+        ReviewIter {
+            next_result: Some(first),
+            next_url: None,
+            page_delay: Duration::ZERO,
+            stop_before: None,
+            total_count: None,
+            empty_pages: 0,
+            deadline: None,
+            hit_deadline: false,
+            page_cache: None,
+            page_source: PageSource::Network,
+            skipped_reviews: 0,
+        }
+    }
+
+    // A single page of canned reviews, for tests which don't scrape.
+    #[cfg(test)]
+    pub fn from_reviews(reviews: Vec<Review>, total_count: Option<u64>) -> Self {
+        let mut it = ReviewIter::new(ReviewResult {
+            next_url: None,
+            reviews,
+            skipped: 0,
+        });
+        it.total_count = total_count;
+        it
+    }
+
+    // Resume pagination from a token returned by next_token(). The place URL
+    // is only used to check that the token belongs to the same place.
+    pub fn from_token(url: &str, token: &str) -> Result<Self, ScrapeError> {
+        check_pagination_url(token)?;
+        let id = place_feature_id(url).ok_or_else(|| {
+            ScrapeError::fatal_parse_error("place URL has no feature ID to check the token against")
+        })?;
+        if !decode_colons(token).contains(&id) {
+            return Err(ScrapeError::fatal_parse_error(format!(
+                "resume token is not for place {}",
+                id
+            )));
+        }
+        let mut it = ReviewIter::new(ReviewResult::default());
+        it.next_result = None;
+        it.next_url = Some(token.to_owned());
+        Ok(it)
+    }
+
+    // Get a token for resuming pagination after the pages returned so far,
+    // even from another process. Tokens are opaque and carry Google's own
+    // pagination state, so they stop working after a while; callers should be
+    // ready to start over from the first page.
+    //
+    // Returns None before the first page has been returned, since that page
+    // can't be fetched without a browser, and after the last page.
+    pub fn next_token(&self) -> Option<&str> {
+        if self.next_result.is_some() {
+            None
+        } else {
+            self.next_url.as_deref()
+        }
+    }
+
+    // The number of reviews the place panel reported before scraping began,
+    // if it could be read.
+    pub fn total_count(&self) -> Option<u64> {
+        self.total_count
+    }
+
+    // Set a delay to wait before fetching each subsequent page, to avoid
+    // tripping rate limits on large scrapes.
+    pub fn set_page_delay(&mut self, delay: Duration) {
+        self.page_delay = delay;
+    }
+
+    // End the iteration early, even in the middle of fetching a page, once
+    // the deadline passes.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    // Share parsed pages with other scrapes through a cache keyed by page URL.
+    pub fn set_page_cache(&mut self, cache: Arc<PageCache>) {
+        self.page_cache = Some(cache);
+    }
+
+    // The number of reviews on the pages returned so far which couldn't be
+    // parsed, and were left out rather than failing their pages.
+    pub fn skipped_reviews(&self) -> usize {
+        self.skipped_reviews
+    }
+
+    // Check if the iteration ended because the deadline passed, in which case
+    // there may be more reviews.
+    pub fn hit_deadline(&self) -> bool {
+        self.hit_deadline
+    }
+
+    // Stop paginating after a page whose reviews are all older than the given
+    // timestamp. This assumes that pages are ordered newest-first; the page
+    // which crosses the boundary is still returned, so callers should filter
+    // out the older reviews themselves.
+    fn set_stop_before(&mut self, timestamp: f64) {
+        self.stop_before = Some(timestamp);
+    }
+
+    pub async fn next(&mut self) -> Result<Option<Vec<Review>>, ScrapeError> {
+        let result = match self.deadline {
+            Some(deadline) => match timeout_at(deadline, self.next_page()).await {
+                Ok(x) => x?,
+                Err(_) => {
+                    self.hit_deadline = true;
+                    self.next_result = None;
+                    self.next_url = None;
+                    return Ok(None);
+                }
+            },
+            None => self.next_page().await?,
+        };
+        if let Some(reviews) = &result {
+            // A page with no parseable reviews can still link to more, so it
+            // shouldn't end the stream unless this keeps happening.
+            if reviews.is_empty() && self.next_url.is_some() {
+                self.empty_pages += 1;
+                if self.empty_pages > MAX_CONSECUTIVE_EMPTY_PAGES {
+                    return Err(ScrapeError::parse_error(format!(
+                        "gave up after {} consecutive pages without reviews",
+                        self.empty_pages
+                    )));
+                }
+                println!(
+                    "warning: review page had no reviews, continuing to next page ({} in a row)",
+                    self.empty_pages
+                );
+            } else if !reviews.is_empty() {
+                self.empty_pages = 0;
+                if let Some(stop_before) = self.stop_before {
+                    if reviews.iter().all(|x| x.timestamp < stop_before) {
+                        self.next_url = None;
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    async fn next_page(&mut self) -> Result<Option<Vec<Review>>, ScrapeError> {
+        if let Some(result) = take(&mut self.next_result) {
+            self.next_url = result.next_url;
+            self.skipped_reviews += result.skipped;
+            Ok(Some(result.reviews))
+        } else if let Some(url) = take(&mut self.next_url) {
+            if let Some(page) = self.page_cache.as_ref().and_then(|x| x.get(&url)) {
+                self.next_url = page.next_url.clone();
+                self.skipped_reviews += page.skipped;
+                return Ok(Some(page.reviews.clone()));
+            }
+            if !self.page_delay.is_zero() {
+                sleep(self.page_delay).await;
+            }
+            check_pagination_url(&url)?;
+            let data = self.page_source.fetch(&url).await?;
+            let split = data.split('\n').last().unwrap();
+            let parsed = parse_logged_reviews(&url, split, 0)?;
+            if let Some(cache) = &self.page_cache {
+                cache.insert(
+                    url,
+                    Arc::new(CachedPage {
+                        reviews: parsed.reviews.clone(),
+                        next_url: parsed.next_url.clone(),
+                        skipped: parsed.skipped,
+                    }),
+                );
+            }
+            self.next_url = parsed.next_url;
+            self.skipped_reviews += parsed.skipped;
+            Ok(Some(parsed.reviews))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// Find the "0x...:0x..." feature ID of a place from the "!1s" segment of its
+// URL's data parameter.
+fn place_feature_id(url: &str) -> Option<String> {
+    let decoded = decode_colons(url);
+    let start = decoded.find("!1s0x")? + 3;
+    let id: String = decoded[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == ':')
+        .collect();
+    id.contains(':').then_some(id)
+}
+
+fn decode_colons(url: &str) -> String {
+    url.replace("%3A", ":").replace("%3a", ":")
+}
+
+// The next page URL is built from the recorded request and a token in
+// Google's response, so make sure it can't send us anywhere else.
+fn check_pagination_url(raw: &str) -> Result<(), ScrapeError> {
+    match Url::parse(raw) {
+        Ok(url) if url.scheme() == "https" && url.host_str().is_some_and(is_google_host) => Ok(()),
+        _ => Err(ScrapeError::fatal_parse_error(format!(
+            "refusing to fetch reviews from unexpected URL: {}",
+            raw
+        ))),
+    }
+}
+
+static PAGINATION_CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+// Network settings for the HTTP client which fetches review pages and place
+// details outside of the browser.
+#[derive(Debug, Default)]
+pub struct PaginationClientOptions {
+    // PEM files of extra root certificates to trust, e.g. for a proxy which
+    // intercepts TLS.
+    pub root_certs: Vec<PathBuf>,
+
+    // Skip certificate validation entirely. This is only meant for testing.
+    pub accept_invalid_certs: bool,
+
+    pub http_version: HttpVersion,
+}
+
+// Which HTTP version to speak to Google over.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum HttpVersion {
+    // Negotiate the version with ALPN.
+    #[default]
+    Auto,
+    Http1,
+    Http2,
+}
+
+impl FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(HttpVersion::Auto),
+            "http1" => Ok(HttpVersion::Http1),
+            "http2" => Ok(HttpVersion::Http2),
+            _ => Err(format!("unknown HTTP version: {}", s)),
+        }
+    }
+}
+
+// Create the pagination client with the given settings. This must happen
+// before any pages are fetched, since the client is otherwise created with
+// the defaults on first use.
+pub fn configure_pagination_client(
+    options: &PaginationClientOptions,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let client = pagination_client_builder(options)?.build()?;
+    PAGINATION_CLIENT
+        .set(client)
+        .map_err(|_| "the pagination client was already created".into())
+}
+
+// Decide whether the pagination client may follow a redirect to the URL,
+// given how many redirects led up to it.
+fn check_redirect(url: &Url, previous: usize) -> Result<(), String> {
+    if previous >= 3 {
+        Err("too many redirects".to_owned())
+    } else if url.host_str().is_some_and(is_google_host) {
+        Ok(())
+    } else {
+        Err(format!("redirect to non-Google URL: {}", url))
+    }
+}
+
+fn pagination_client_builder(
+    options: &PaginationClientOptions,
+) -> Result<reqwest::ClientBuilder, Box<dyn Error + Send + Sync>> {
+    let mut builder = reqwest::Client::builder().redirect(Policy::custom(|attempt| {
+        match check_redirect(attempt.url(), attempt.previous().len()) {
+            Ok(()) => attempt.follow(),
+            Err(msg) => attempt.error(msg),
+        }
+    }));
+    for path in &options.root_certs {
+        let pem =
+            std::fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if options.accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(match options.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    })
+}
+
+fn pagination_client() -> &'static reqwest::Client {
+    PAGINATION_CLIENT.get_or_init(|| {
+        pagination_client_builder(&Default::default())
+            .expect("configure pagination HTTP client")
+            .build()
+            .expect("create pagination HTTP client")
+    })
+}
+
+// The most redirects to follow when resolving a short link.
+const MAX_SHORT_LINK_REDIRECTS: usize = 5;
+
+// Check for a shortened Google Maps link, like the ones from the share button.
+pub fn is_short_link(raw: &str) -> bool {
+    Url::parse(raw).is_ok_and(|url| {
+        url.scheme() == "https" && matches!(url.host_str(), Some("maps.app.goo.gl" | "goo.gl"))
+    })
+}
+
+// Follow a shortened link to the Google page it points to, without loading
+// anything in a browser.
+pub async fn resolve_short_link(raw: &str) -> Result<String, ScrapeError> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    let client = CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(Policy::none())
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("create short link HTTP client")
+    });
+    let mut url = raw.to_owned();
+    for _ in 0..MAX_SHORT_LINK_REDIRECTS {
+        let resp = client.get(&url).send().await?;
+        if !resp.status().is_redirection() {
+            return Err(ScrapeError::fatal_parse_error(format!(
+                "short link {} did not redirect (status {})",
+                raw,
+                resp.status()
+            )));
+        }
+        let location = resp
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| Url::parse(&url).ok()?.join(x).ok())
+            .ok_or_else(|| {
+                ScrapeError::fatal_parse_error(format!("short link {} has a bad redirect", raw))
+            })?;
+        url = location.to_string();
+        if location.host_str().is_some_and(is_google_host) {
+            return Ok(url);
+        } else if !is_short_link(&url) {
+            return Err(ScrapeError::fatal_parse_error(format!(
+                "short link {} redirected to non-Google URL: {}",
+                raw, url
+            )));
+        }
+    }
+    Err(ScrapeError::fatal_parse_error(format!(
+        "short link {} redirected too many times",
+        raw
+    )))
+}
+
+// The scraping operations used by the API handlers. This is implemented by
+// Client, and can be implemented by other types to test the handlers without a
+// real browser.
+//
+// Scrapers are reused across requests, so each operation must set (or clear)
+// the emulated location before it navigates anywhere, rather than relying on
+// state left behind by a previous request.
+pub trait Scraper: Send + 'static {
+    fn search(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+    ) -> impl Future<Output = Result<SearchResult, ScrapeError>> + Send;
+
+    // Like search(), but also send each result of a multiple-result search to
+    // found as soon as it's ready, rather than only once they all are. By
+    // default, the results are sent after the whole search finishes.
+    fn search_incremental(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+        found: Sender<LocationInfo>,
+    ) -> impl Future<Output = Result<SearchResult, ScrapeError>> + Send {
+        async move {
+            let result = self.search(search, location).await?;
+            if let SearchResult::Multiple(results) = &result {
+                for x in results {
+                    if found.send(x.clone()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Ok(result)
+        }
+    }
+
+    fn list_reviews(
+        &mut self,
+        url: &str,
+        location: &GeoLocation,
+        options: &ReviewListOptions,
+    ) -> impl Future<Output = Result<ReviewIter, ScrapeError>> + Send;
+
+    // List up to max_count URLs from the place's photo gallery. If the
+    // gallery can't be found, an empty list is returned.
+    fn list_photos(
+        &mut self,
+        url: &str,
+        max_count: usize,
+    ) -> impl Future<Output = Result<Vec<String>, ScrapeError>> + Send;
+
+    // Read the aggregate rating and review count from the place panel,
+    // without loading any individual reviews.
+    fn place_rating(
+        &mut self,
+        url: &str,
+    ) -> impl Future<Output = Result<PlaceRating, ScrapeError>> + Send;
+
+    // Replace any underlying browser session with a fresh one.
+    fn reconnect(&mut self) -> impl Future<Output = Result<(), ScrapeError>> + Send;
+
+    fn close(self) -> impl Future<Output = Result<(), ScrapeError>> + Send;
+}
+
+// A script run in the page, named so that recordings can refer to it.
+#[derive(Clone, Copy)]
+pub struct Script {
+    pub name: &'static str,
+    source: &'static str,
+}
+
+macro_rules! injected_script {
+    ($name:literal) => {
+        Script {
+            name: $name,
+            source: include_str!(concat!("injected_scripts/", $name, ".js")),
+        }
+    };
+}
+
+const RECORDED_REVIEW_RESPONSES: Script = Script {
+    name: "recorded_review_responses",
+    source: "return window.recordedReviewResponses",
+};
+
+// Everything scraping reads from a page once it has been loaded. This is
+// implemented by BrowserPage, and by ReplayPage to run the same scraping code
+// against a recording.
+pub trait PageDriver: Sync {
+    fn execute(
+        &self,
+        script: Script,
+        args: Vec<serde_json::Value>,
+    ) -> impl Future<Output = WebDriverResult<serde_json::Value>> + Send;
+
+    fn current_url(&self) -> impl Future<Output = WebDriverResult<Url>> + Send;
+
+    // Read an attribute of the first element matching the XPath, or return
+    // None if nothing matches.
+    fn find_attr(
+        &self,
+        xpath: &'static str,
+        attr: &'static str,
+    ) -> impl Future<Output = WebDriverResult<Option<Option<String>>>> + Send;
+
+    // Where the review pages after the first one should come from.
+    fn page_source(&self) -> PageSource;
+}
+
+// Run the script and convert its result. Conversion errors are driver errors,
+// just like they are for thirtyfour's ScriptRet::convert().
+async fn run_script<D: PageDriver, T: DeserializeOwned>(
+    driver: &D,
+    script: Script,
+    args: Vec<serde_json::Value>,
+) -> Result<T, ScrapeError> {
+    let result = driver.execute(script, args).await?;
+    Ok(serde_json::from_value(result).map_err(WebDriverError::from)?)
+}
+
+// A page loaded in a client's browser. If the client has a recorder, every
+// result read from the page is recorded under the page's name.
+pub struct BrowserPage<'a> {
+    driver: &'a WebDriver,
+    recorder: Option<&'a Arc<Recorder>>,
+    page: String,
+}
+
+impl<'a> PageDriver for BrowserPage<'a> {
+    async fn execute(
+        &self,
+        script: Script,
+        args: Vec<serde_json::Value>,
+    ) -> WebDriverResult<serde_json::Value> {
+        let result = self
+            .driver
+            .execute(script.source, args.clone())
+            .await
+            .map(|x| x.json().clone());
+        if let Some(recorder) = self.recorder {
+            recorder.record(&Interaction::Script {
+                page: self.page.clone(),
+                name: script.name.to_owned(),
+                args,
+                result: to_recorded(&result),
+            });
+        }
+        result
+    }
+
+    async fn current_url(&self) -> WebDriverResult<Url> {
+        let result = self.driver.current_url().await;
+        if let Some(recorder) = self.recorder {
+            recorder.record(&Interaction::CurrentUrl {
+                page: self.page.clone(),
+                result: to_recorded(&result.as_ref().map(|x| x.to_string())),
+            });
+        }
+        result
+    }
+
+    async fn find_attr(
+        &self,
+        xpath: &'static str,
+        attr: &'static str,
+    ) -> WebDriverResult<Option<Option<String>>> {
+        let result = match self.driver.find_all(By::XPath(xpath)).await {
+            Ok(elements) => match elements.first() {
+                Some(x) => x.attr(attr).await.map(Some),
+                None => Ok(None),
+            },
+            Err(e) => Err(e),
+        };
+        if let Some(recorder) = self.recorder {
+            recorder.record(&Interaction::FindAttr {
+                page: self.page.clone(),
+                xpath: xpath.to_owned(),
+                attr: attr.to_owned(),
+                result: to_recorded(&result),
+            });
+        }
+        result
+    }
+
+    fn page_source(&self) -> PageSource {
+        match self.recorder {
+            Some(recorder) => PageSource::Recording(recorder.clone()),
+            None => PageSource::Network,
+        }
+    }
+}
+
+// Where ReviewIter gets the pages after the first one.
+#[derive(Clone, Default)]
+pub enum PageSource {
+    #[default]
+    Network,
+    // Fetch pages from Google, recording each response.
+    Recording(Arc<Recorder>),
+    // Play back recorded responses without fetching anything.
+    Replay(Arc<Replay>),
+}
+
+impl PageSource {
+    async fn fetch(&self, url: &str) -> Result<String, ScrapeError> {
+        match self {
+            PageSource::Network => fetch_review_page(url).await,
+            PageSource::Recording(recorder) => {
+                let result = fetch_review_page(url).await;
+                recorder.record(&Interaction::Response {
+                    url: url.to_owned(),
+                    result: to_recorded(&result),
+                });
+                result
+            }
+            PageSource::Replay(replay) => replay.response(url),
+        }
+    }
+}
+
+async fn fetch_review_page(url: &str) -> Result<String, ScrapeError> {
+    let resp = pagination_client().get(url).send().await?;
+    let data = resp.bytes().await?;
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+// How review responses are captured from the page.
+#[derive(Clone, Copy, Debug)]
+pub enum CaptureStrategy {
+    // Only record XMLHttpRequest responses.
+    Xhr,
+    // Record both XMLHttpRequest and fetch() responses.
+    XhrFetch,
+}
+
+impl FromStr for CaptureStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xhr" => Ok(CaptureStrategy::Xhr),
+            "xhr-fetch" => Ok(CaptureStrategy::XhrFetch),
+            _ => Err(format!("unknown capture strategy: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    pub driver: String,
+    pub headless: bool,
+
+    // How many times to redo the whole search (including navigation) if the
+    // results page never loads.
+    pub search_retries: usize,
+
+    pub capture_strategy: CaptureStrategy,
+
+    // The Google Maps URL that searches start from, which can be pointed at
+    // a mirror or local fixture.
+    pub maps_base_url: String,
+
+    pub chrome_binary: Option<String>,
+
+    // Extra command-line flags to pass to Chrome.
+    pub chrome_args: Vec<String>,
+
+    // If set, each client gets its own Chrome profile in a fresh directory
+    // under this one, which is removed when the client is closed. This path
+    // is interpreted on the machine running the driver.
+    pub user_data_base: Option<PathBuf>,
+
+    // If set, each client uses a Chrome profile under this directory which is
+    // kept across restarts, so that consent choices and caches carry over.
+    // Clients never share a profile at the same time, but they do inherit
+    // state from whichever client used the profile before them.
+    pub persistent_profile: Option<PathBuf>,
+
+    // If non-zero, search results which the results list didn't give any
+    // details for are followed up by fetching their place pages, with up to
+    // this many requests at once.
+    pub detail_concurrency: usize,
+
+    // Fail operations if the emulated location can't be set, rather than
+    // falling back to the browser's own location.
+    pub strict_location: bool,
+
+    // How many times to click the "more reviews" button or scroll the review
+    // panel before giving up on a review request being recorded. Panels which
+    // load reviews as they're scrolled may need more than one.
+    pub more_reviews_clicks: usize,
+
+    // Prefixes of the results list's label (e.g. "Results for") and of the
+    // message shown when a search has no results (e.g. "Google Maps can't
+    // find"), covering each locale the browser might be using.
+    pub results_labels: Vec<String>,
+    pub not_found_texts: Vec<String>,
+}
+
+// Defaults for ClientOptions::results_labels in a handful of common locales.
+pub const DEFAULT_RESULTS_LABELS: &[&str] = &[
+    "Results for",
+    "Resultados de",
+    "Resultados para",
+    "Résultats pour",
+    "Ergebnisse für",
+    "Risultati per",
+];
+
+// Defaults for ClientOptions::not_found_texts in a handful of common locales.
+pub const DEFAULT_NOT_FOUND_TEXTS: &[&str] = &[
+    "Google Maps can't find",
+    "Google Maps no encuentra",
+    "Google Maps ne trouve pas",
+    "Google Maps kann",
+    "Google Maps non riesce a trovare",
+    "O Google Maps não encontrou",
+];
+
+pub struct Client {
+    driver: WebDriver,
+    dev_tools: ChromeDevTools,
+    options: ClientOptions,
+    user_data_dir: Option<PathBuf>,
+    profile_slot: Option<ProfileSlot>,
+    recorder: Option<Arc<Recorder>>,
+}
+
+// Prefix of the per-client profile directories created under user_data_base.
+const USER_DATA_DIR_PREFIX: &str = "review-plot-";
+
+// Prefix of the numbered profile directories kept under persistent_profile.
+const PERSISTENT_PROFILE_PREFIX: &str = "profile-";
+
+// A claim on one of the numbered persistent profiles, since Chrome can't run
+// two browsers from the same profile. The lowest free number is claimed, so
+// that a restarted server picks up the same profiles again.
+struct ProfileSlot(usize);
+
+static PROFILE_SLOTS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+impl ProfileSlot {
+    fn claim() -> Self {
+        let mut slots = PROFILE_SLOTS.lock().unwrap();
+        let slot = (0..).find(|x| !slots.contains(x)).unwrap();
+        slots.insert(slot);
+        ProfileSlot(slot)
+    }
+
+    fn path(&self, base: &Path) -> PathBuf {
+        base.join(format!("{}{}", PERSISTENT_PROFILE_PREFIX, self.0))
+    }
+}
+
+impl Drop for ProfileSlot {
+    fn drop(&mut self) {
+        PROFILE_SLOTS.lock().unwrap().remove(&self.0);
+    }
+}
+
+// Remove profile directories left behind by previous runs which didn't shut
+// down cleanly. This assumes that no other server shares the base directory.
+pub fn remove_stale_user_data_dirs(base: &Path) -> std::io::Result<()> {
+    if !base.exists() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(base)? {
+        let path = entry?.path();
+        let is_ours = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .is_some_and(|x| x.starts_with(USER_DATA_DIR_PREFIX));
+        if is_ours {
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                println!("error removing stale profile {}: {}", path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn new_user_data_dir(base: &Path) -> std::io::Result<PathBuf> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let path = base.join(format!(
+        "{}{}-{}",
+        USER_DATA_DIR_PREFIX,
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::SeqCst)
+    ));
+    std::fs::create_dir_all(&path)?;
+    Ok(path)
+}
+
+fn remove_user_data_dir(path: &Path) {
+    if let Err(e) = std::fs::remove_dir_all(path) {
+        println!("error removing profile {}: {}", path.display(), e);
+    }
+}
+
+// Start a browser session, with its own profile directory if one is given.
+// A temporary profile directory is removed if the session can't be started.
+async fn start_browser(
+    options: &ClientOptions,
+    user_data_dir: Option<&Path>,
+    profile_slot: Option<&ProfileSlot>,
+) -> WebDriverResult<WebDriver> {
+    let mut caps = DesiredCapabilities::chrome();
+    if options.headless {
+        caps.add_arg("--headless=new")?;
+    }
+    caps.add_arg("--window-size=1920,1080")?;
+    for arg in &options.chrome_args {
+        caps.add_arg(arg)?;
+    }
+    if let Some(binary) = &options.chrome_binary {
+        caps.set_binary(binary)?;
+    }
+    if let Some(dir) = user_data_dir {
+        caps.add_arg(&format!("--user-data-dir={}", dir.display()))?;
+    }
+    if let (Some(slot), Some(base)) = (profile_slot, &options.persistent_profile) {
+        caps.add_arg(&format!("--user-data-dir={}", slot.path(base).display()))?;
+    }
+    let result = WebDriver::new(&options.driver, caps).await;
+    if let (Err(_), Some(dir)) = (&result, user_data_dir) {
+        remove_user_data_dir(dir);
+    }
+    result
+}
+
+fn create_user_data_dir(options: &ClientOptions) -> WebDriverResult<Option<PathBuf>> {
+    match &options.user_data_base {
+        Some(base) => new_user_data_dir(base)
+            .map(Some)
+            .map_err(|e| WebDriverError::RequestFailed(format!("create profile directory: {}", e))),
+        None => Ok(None),
+    }
+}
+
+impl Client {
+    pub async fn new(options: &ClientOptions) -> WebDriverResult<Client> {
+        let user_data_dir = create_user_data_dir(options)?;
+        let profile_slot = match &options.persistent_profile {
+            Some(base) => {
+                let slot = ProfileSlot::claim();
+                std::fs::create_dir_all(slot.path(base)).map_err(|e| {
+                    WebDriverError::RequestFailed(format!("create profile directory: {}", e))
+                })?;
+                Some(slot)
+            }
+            None => None,
+        };
+        let driver =
+            start_browser(options, user_data_dir.as_deref(), profile_slot.as_ref()).await?;
+        let tools = ChromeDevTools::new(driver.handle.clone());
+        Ok(Client {
+            driver: driver,
+            dev_tools: tools,
+            options: options.clone(),
+            user_data_dir,
+            profile_slot,
+            recorder: None,
+        })
+    }
+
+    // Replace the browser session with a fresh one created from the same
+    // options. The old session is closed first (on a best-effort basis), so
+    // that two browsers never run at once for one client. A persistent
+    // profile is kept, while a temporary one is swapped for a new directory.
+    pub async fn reconnect(&mut self) -> WebDriverResult<()> {
+        if let Err(e) = self.driver.close_window().await {
+            println!("error closing old browser session: {}", e);
+        }
+        let user_data_dir = create_user_data_dir(&self.options)?;
+        let driver = start_browser(
+            &self.options,
+            user_data_dir.as_deref(),
+            self.profile_slot.as_ref(),
+        )
+        .await?;
+        if let Some(dir) = replace(&mut self.user_data_dir, user_data_dir) {
+            remove_user_data_dir(&dir);
+        }
+        self.dev_tools = ChromeDevTools::new(driver.handle.clone());
+        self.driver = driver;
+        Ok(())
+    }
+
+    async fn search_once(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+        found: Option<&Sender<LocationInfo>>,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.prepare(Some(location)).await?;
+        // Persistent profiles are meant to keep their consent cookies.
+        if self.options.persistent_profile.is_none() {
+            self.driver.delete_all_cookies().await?;
+        }
+        self.goto_checked(&format!(
+            "{}/@{},{},15z?entry=ttu",
+            self.options.maps_base_url.trim_end_matches('/'),
+            location.latitude,
+            location.longitude,
+        ))
+        .await?;
+        // The search box may not have rendered yet on a slow load.
+        wait_for_scrape_result(&self.driver, Duration::from_secs(1), |driver| {
+            enter_search_query(driver, search)
+        })
+        .await
+        .map_err(|e| e.in_phase(ScrapePhase::SearchBox))?;
+
+        scrape_search_result(
+            &self.page("search", search),
+            &self.options,
+            self.options.detail_concurrency,
+            found,
+        )
+        .await
+    }
+
+    // Set (or clear) the emulated location. This is the first command of every
+    // operation, so if the browser session died while the client sat idle in
+    // the pool, it fails here; in that case, start a new session and try once
+    // more.
+    //
+    // Unless strict_location is set, a browser which doesn't support the
+    // override (e.g. a remote grid without CDP) is left at its own location.
+    async fn prepare(&mut self, location: Option<&GeoLocation>) -> Result<(), ScrapeError> {
+        let result = match apply_location(&self.dev_tools, location).await {
+            Err(e) if is_dead_session(&e) => {
+                println!("browser session is gone ({}); starting a new one", e);
+                self.reconnect().await?;
+                apply_location(&self.dev_tools, location).await
+            }
+            x => x,
+        };
+        match result {
+            Err(e) if !self.options.strict_location && !is_dead_session(&e) => {
+                println!("warning: failed to override browser location: {}", e);
+                Ok(())
+            }
+            x => Ok(x?),
+        }
+    }
+
+    // Navigate to the URL, failing if we end up on an unrelated page.
+    async fn goto_checked(&self, url: &str) -> Result<(), ScrapeError> {
+        self.driver.goto(url).await?;
+        let current = self.driver.current_url().await?;
+        if is_unexpected_redirect(url, &current) {
+            Err(ScrapeError::UnexpectedRedirect(current.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Record what an operation reads from the current page, if this client
+    // has a recorder, under the operation's name and target.
+    fn page(&self, operation: &str, target: &str) -> BrowserPage<'_> {
+        BrowserPage {
+            driver: &self.driver,
+            recorder: self.recorder.as_ref(),
+            page: format!("{} {}", operation, target),
+        }
+    }
+
+    // Start recording what this client reads from pages and from Google, so
+    // that it can be played back with ReplayScraper.
+    pub fn set_recorder(&mut self, recorder: Arc<Recorder>) {
+        self.recorder = Some(recorder);
+    }
+
+    pub async fn close(self) -> WebDriverResult<()> {
+        let result = self.driver.close_window().await;
+        if let Some(dir) = &self.user_data_dir {
+            remove_user_data_dir(dir);
+        }
+        result
+    }
+}
+
+impl Client {
+    async fn search_filled(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+        found: Option<&Sender<LocationInfo>>,
+    ) -> Result<SearchResult, ScrapeError> {
+        // A page stuck in a transient state often recovers on a fresh
+        // navigation, so retry the whole search on timeouts.
+        let mut retries = 0;
+        loop {
+            match self.search_once(search, location, found).await {
+                Err(e)
+                    if matches!(e.root(), ScrapeError::TimeoutError(_, _))
+                        && retries < self.options.search_retries =>
+                {
+                    retries += 1;
+                }
+                x => break x,
+            }
+        }
+    }
+}
+
+impl Scraper for Client {
+    async fn search(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.search_filled(search, location, None).await
+    }
+
+    async fn search_incremental(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+        found: Sender<LocationInfo>,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.search_filled(search, location, Some(&found)).await
+    }
+
+    async fn list_reviews(
+        &mut self,
+        url: &str,
+        location: &GeoLocation,
+        options: &ReviewListOptions,
+    ) -> Result<ReviewIter, ScrapeError> {
+        self.prepare(Some(location)).await?;
+
+        // Intentionally clear any scripts on the page.
+        self.driver.goto("https://google.com").await?;
+        self.goto_checked(url).await?;
+
+        scrape_reviews(&self.page("reviews", url), &self.options, options).await
+    }
+
+    async fn list_photos(
+        &mut self,
+        url: &str,
+        max_count: usize,
+    ) -> Result<Vec<String>, ScrapeError> {
+        self.prepare(None).await?;
+        self.goto_checked(url).await?;
+        scrape_photos(&self.page("photos", url), max_count).await
+    }
 
-impl ScrapeError {
-    pub fn timeout<S: Display>(msg: S, inner: Option<ScrapeError>) -> Self {
-        ScrapeError::TimeoutError(format!("{}", msg), inner.map(|x| Box::new(x)))
+    async fn place_rating(&mut self, url: &str) -> Result<PlaceRating, ScrapeError> {
+        self.prepare(None).await?;
+        self.goto_checked(url).await?;
+        scrape_place_rating(&self.page("rating", url)).await
     }
 
-    pub fn parse_error<S: Display>(msg: S) -> Self {
-        ScrapeError::ParseError(format!("{}", msg))
+    async fn reconnect(&mut self) -> Result<(), ScrapeError> {
+        Ok(Client::reconnect(self).await?)
     }
 
-    pub fn fatal_parse_error<S: Display>(msg: S) -> Self {
-        ScrapeError::FatalParseError(format!("{}", msg))
+    async fn close(self) -> Result<(), ScrapeError> {
+        Ok(Client::close(self).await?)
     }
 }
 
-pub struct ReviewIter {
-    next_result: Option<ReviewResult>,
-    next_url: Option<String>,
+// Search results are read the same way from a live page or a recording, once
+// the query has been submitted. Each result of a multiple-result search is
+// sent to found (if given) as soon as its details are filled in; see
+// fill_details.
+pub async fn scrape_search_result<D: PageDriver>(
+    driver: &D,
+    options: &ClientOptions,
+    detail_concurrency: usize,
+    found: Option<&Sender<LocationInfo>>,
+) -> Result<SearchResult, ScrapeError> {
+    wait_for_scrape_result(driver, Duration::from_secs(1), |driver| {
+        decode_search_result(driver, options, detail_concurrency, found)
+    })
+    .await
+    .map_err(|e| e.in_phase(ScrapePhase::Results))
 }
 
-impl<'a> ReviewIter {
-    fn new(first: ReviewResult) -> Self {
-        ReviewIter {
-            next_result: Some(first),
-            next_url: None,
+// Open the review panel of the loaded place page and read the first page of
+// reviews. Later pages are fetched as the iterator is advanced, from wherever
+// the driver's page_source() says.
+pub async fn scrape_reviews<D: PageDriver>(
+    driver: &D,
+    client_options: &ClientOptions,
+    options: &ReviewListOptions,
+) -> Result<ReviewIter, ScrapeError> {
+    // Load script that will dump all requests. The script checks whether it
+    // has already patched the page, so running it again on a reused client
+    // only resets the recorded responses.
+    let patch_fetch = matches!(client_options.capture_strategy, CaptureStrategy::XhrFetch);
+    driver
+        .execute(
+            injected_script!("dump_review_requests"),
+            vec![serde_json::Value::Bool(patch_fetch)],
+        )
+        .await?;
+    // Check for a sign-in prompt on every attempt, since it would otherwise
+    // look like a missing button until the retries run out.
+    let count = wait_for_scrape_result(driver, Duration::from_secs(1), |driver| async {
+        if has_sign_in_wall(driver).await? {
+            Err(ScrapeError::SignInRequired)
+        } else {
+            click_more_reviews_button(driver).await
+        }
+    })
+    .await
+    .map_err(|e| e.in_phase(ScrapePhase::MoreButton))?;
+    if count > 0 {
+        for _ in 1..client_options.more_reviews_clicks {
+            sleep(Duration::from_secs(1)).await;
+            if load_more_reviews(driver).await? {
+                break;
+            }
         }
     }
 
-    pub async fn next(&mut self) -> Result<Option<Vec<Review>>, ScrapeError> {
-        if let Some(result) = take(&mut self.next_result) {
-            self.next_url = result.next_url;
-            Ok(Some(result.reviews))
-        } else if let Some(url) = take(&mut self.next_url) {
-            let resp = reqwest::get(&url).await?;
-            let data: Vec<u8> = resp.bytes().await?.into();
-            let split = data.split(|x| *x == b'\n').last().unwrap();
-            let parsed = parse_logged_reviews(&url, &String::from_utf8_lossy(split))?;
-            self.next_url = parsed.next_url;
-            Ok(Some(parsed.reviews))
-        } else {
-            Ok(None)
+    // The panel has loaded by now, so probe the review count once (the same
+    // number place_rating() reports) before committing to a long scrape.
+    let total_count = get_place_rating(driver)
+        .await
+        .ok()
+        .and_then(|x| x.review_count);
+    if let (Some(total), Some(max)) = (total_count, options.max_total_reviews) {
+        if total > max {
+            return Err(ScrapeError::TooManyReviews(total, max));
         }
     }
+
+    let mut iter = if count == 0 {
+        ReviewIter::new(ReviewResult::default())
+    } else {
+        let mut reviews = wait_for_reviews(driver, options.raw_samples).await?;
+        if let Some(language) = &options.language {
+            if select_review_language(driver, language).await? {
+                reviews = wait_for_reviews(driver, options.raw_samples).await?;
+            }
+        }
+        // Stopping at since only works if the newest reviews come first.
+        let sort = options
+            .google_sort
+            .or(options.since.map(|_| GoogleSort::Newest));
+        let mut sorted = None;
+        if let Some(sort) = sort {
+            if sort_reviews(driver, sort).await? {
+                sorted = Some(sort);
+                reviews = wait_for_reviews(driver, options.raw_samples).await?;
+            }
+        }
+        let mut iter = ReviewIter::new(reviews);
+        if let (Some(GoogleSort::Newest), Some(since)) = (sorted, options.since) {
+            iter.set_stop_before(since);
+        }
+        iter
+    };
+    iter.total_count = total_count;
+    iter.page_source = driver.page_source();
+    Ok(iter)
 }
 
-pub struct Client {
-    driver: WebDriver,
-    dev_tools: ChromeDevTools,
+// Returns false if the language filter control couldn't be found, in which
+// case the unfiltered reviews should be used.
+async fn select_review_language<D: PageDriver>(
+    driver: &D,
+    language: &str,
+) -> Result<bool, ScrapeError> {
+    run_script(
+        driver,
+        injected_script!("select_review_language"),
+        vec![serde_json::Value::String(language.to_owned())],
+    )
+    .await
 }
 
-impl Client {
-    pub async fn new(driver: &str, headless: bool) -> WebDriverResult<Client> {
-        let mut caps = DesiredCapabilities::chrome();
-        if headless {
-            caps.add_arg("--headless=new")?;
-        }
-        caps.add_arg("--window-size=1920,1080")?;
-        let driver = WebDriver::new(driver, caps).await?;
-        let tools = ChromeDevTools::new(driver.handle.clone());
-        Ok(Client {
-            driver: driver,
-            dev_tools: tools,
-        })
+// Returns false if the sort control couldn't be found, in which case the
+// reviews remain in Google's default order.
+async fn sort_reviews<D: PageDriver>(driver: &D, sort: GoogleSort) -> Result<bool, ScrapeError> {
+    run_script(
+        driver,
+        injected_script!("sort_reviews"),
+        vec![serde_json::to_value(sort.labels())?],
+    )
+    .await
+}
+
+// If the gallery can't be found, an empty list is returned.
+pub async fn scrape_photos<D: PageDriver>(
+    driver: &D,
+    max_count: usize,
+) -> Result<Vec<String>, ScrapeError> {
+    match wait_for_scrape_result(driver, Duration::from_secs(1), |driver| {
+        get_photo_urls(driver, max_count)
+    })
+    .await
+    {
+        Err(ScrapeError::TimeoutError(_, _)) => Ok(vec![]),
+        x => x,
+    }
+}
+
+pub async fn scrape_place_rating<D: PageDriver>(driver: &D) -> Result<PlaceRating, ScrapeError> {
+    match wait_for_scrape_result(driver, Duration::from_secs(1), get_place_rating).await {
+        Err(ScrapeError::TimeoutError(_, _)) => Ok(PlaceRating::default()),
+        x => x,
     }
+}
 
-    pub async fn search(
-        &mut self,
-        search: &str,
-        location: &GeoLocation,
-    ) -> Result<SearchResult, ScrapeError> {
-        set_location(&self.dev_tools, location).await?;
-        self.driver.delete_all_cookies().await?;
-        self.driver
-            .goto(format!(
-                "https://www.google.com/maps/@{},{},15z?entry=ttu",
-                location.latitude, location.longitude,
-            ))
-            .await?;
-        let query = self.driver.find(By::Name("q")).await?;
-        query.focus().await?;
-        query.send_keys(search).await?;
-        query.send_keys("\n").await?;
-
-        Ok(
-            wait_for_scrape_result(&self.driver, Duration::from_secs(1), decode_search_result)
-                .await?,
-        )
+fn is_unexpected_redirect(requested: &str, current: &Url) -> bool {
+    let host = current.host_str().unwrap_or_default();
+    if host.starts_with("consent.") || current.path().starts_with("/sorry") {
+        return true;
     }
+    let requested_host = Url::parse(requested)
+        .ok()
+        .and_then(|x| x.host_str().map(|x| x.to_owned()))
+        .unwrap_or_default();
+    // Moving between Google domains (e.g. google.com to www.google.com) is
+    // expected, but leaving them entirely is not.
+    host != requested_host && !(is_google_host(host) && is_google_host(&requested_host))
+}
 
-    pub async fn list_reviews(
-        &mut self,
-        url: &str,
-        location: &GeoLocation,
-    ) -> Result<ReviewIter, ScrapeError> {
-        set_location(&self.dev_tools, location).await?;
+// What comes after "google." in each of Google's own domains, from
+// https://www.google.com/supported_domains.
+const GOOGLE_DOMAIN_SUFFIXES: &[&str] = &[
+    "com", "ad", "ae", "com.af", "com.ag", "al", "am", "co.ao", "com.ar", "as", "at", "com.au",
+    "az", "ba", "com.bd", "be", "bf", "bg", "com.bh", "bi", "bj", "com.bn", "com.bo", "com.br",
+    "bs", "bt", "co.bw", "by", "com.bz", "ca", "cat", "cd", "cf", "cg", "ch", "ci", "co.ck", "cl",
+    "cm", "cn", "com.co", "co.cr", "com.cu", "cv", "com.cy", "cz", "de", "dj", "dk", "dm",
+    "com.do", "dz", "com.ec", "ee", "com.eg", "es", "com.et", "fi", "com.fj", "fm", "fr", "ga",
+    "ge", "gg", "com.gh", "com.gi", "gl", "gm", "gr", "com.gt", "gy", "com.hk", "hn", "hr", "ht",
+    "hu", "co.id", "ie", "co.il", "im", "co.in", "iq", "is", "it", "je", "com.jm", "jo", "co.jp",
+    "co.ke", "com.kh", "ki", "kg", "co.kr", "com.kw", "kz", "la", "com.lb", "li", "lk", "co.ls",
+    "lt", "lu", "lv", "com.ly", "co.ma", "md", "me", "mg", "mk", "ml", "com.mm", "mn", "com.mt",
+    "mu", "mv", "mw", "com.mx", "com.my", "co.mz", "com.na", "com.ng", "com.ni", "ne", "nl", "no",
+    "com.np", "nr", "nu", "co.nz", "com.om", "com.pa", "com.pe", "com.pg", "com.ph", "com.pk",
+    "pl", "pn", "com.pr", "ps", "pt", "com.py", "com.qa", "ro", "rs", "ru", "rw", "com.sa",
+    "com.sb", "sc", "se", "com.sg", "sh", "si", "sk", "com.sl", "sn", "so", "sm", "sr", "st",
+    "com.sv", "td", "tg", "co.th", "com.tj", "tl", "tm", "tn", "to", "com.tr", "tt", "com.tw",
+    "co.tz", "com.ua", "co.ug", "co.uk", "com.uy", "co.uz", "com.vc", "co.ve", "co.vi", "com.vn",
+    "vu", "ws", "co.za", "co.zm", "co.zw",
+];
 
-        // Intentionally clear any scripts on the page.
-        self.driver.goto("https://google.com").await?;
-        self.driver.goto(url).await?;
+// Check for a Google domain such as google.com, www.google.de, or
+// maps.google.co.uk.
+pub fn is_google_host(host: &str) -> bool {
+    GOOGLE_DOMAIN_SUFFIXES.iter().any(|suffix| {
+        host.strip_suffix(suffix)
+            .and_then(|x| x.strip_suffix("google."))
+            .is_some_and(|subdomain| subdomain.is_empty() || subdomain.ends_with('.'))
+    })
+}
 
-        // Load script that will dump all requests.
-        self.driver
-            .execute(
-                include_str!("injected_scripts/dump_review_requests.js"),
-                vec![],
-            )
-            .await?;
-        let count = wait_for_scrape_result(
-            &self.driver,
-            Duration::from_secs(1),
-            click_more_reviews_button,
-        )
-        .await?;
+fn is_dead_session(e: &WebDriverError) -> bool {
+    matches!(
+        e,
+        WebDriverError::InvalidSessionId(_)
+            | WebDriverError::NoSuchWindow(_)
+            | WebDriverError::RequestFailed(_)
+    )
+}
 
-        if count == 0 {
-            Ok(ReviewIter::new(ReviewResult::default()))
-        } else {
-            let reviews =
-                wait_for_scrape_result(&self.driver, Duration::from_secs(1), get_logged_reviews)
-                    .await?;
-            Ok(ReviewIter::new(reviews))
-        }
+// The DevTools commands a client sends to emulate its location, so that which
+// ones are sent can be checked without a browser.
+trait DevTools: Sync {
+    fn execute_cdp(
+        &self,
+        command: &str,
+    ) -> impl Future<Output = WebDriverResult<serde_json::Value>> + Send;
+
+    fn execute_cdp_with_params(
+        &self,
+        command: &str,
+        params: serde_json::Value,
+    ) -> impl Future<Output = WebDriverResult<serde_json::Value>> + Send;
+}
+
+impl DevTools for ChromeDevTools {
+    async fn execute_cdp(&self, command: &str) -> WebDriverResult<serde_json::Value> {
+        ChromeDevTools::execute_cdp(self, command).await
     }
 
-    pub async fn close(self) -> WebDriverResult<()> {
-        self.driver.close_window().await
+    async fn execute_cdp_with_params(
+        &self,
+        command: &str,
+        params: serde_json::Value,
+    ) -> WebDriverResult<serde_json::Value> {
+        ChromeDevTools::execute_cdp_with_params(self, command, params).await
+    }
+}
+
+// A browser keeps its location override between requests, so operations
+// without a location must clear it rather than leave it alone.
+async fn apply_location<T: DevTools>(
+    dev_tools: &T,
+    location: Option<&GeoLocation>,
+) -> WebDriverResult<()> {
+    match location {
+        Some(location) => set_location(dev_tools, location).await,
+        None => clear_location(dev_tools).await,
     }
 }
 
-async fn set_location(dev_tools: &ChromeDevTools, location: &GeoLocation) -> WebDriverResult<()> {
+async fn clear_location<T: DevTools>(dev_tools: &T) -> WebDriverResult<()> {
+    dev_tools
+        .execute_cdp("Emulation.clearGeolocationOverride")
+        .await
+        .map(|_| ())
+}
+
+async fn set_location<T: DevTools>(dev_tools: &T, location: &GeoLocation) -> WebDriverResult<()> {
     dev_tools
         .execute_cdp_with_params(
             "Emulation.setGeolocationOverride",
@@ -227,14 +1674,18 @@ async fn set_location(dev_tools: &ChromeDevTools, location: &GeoLocation) -> Web
         .and_then(|_| Ok(()))
 }
 
-async fn wait_for_scrape_result<'a, T, Fut, F>(
-    driver: &'a WebDriver,
+// Retry f until it succeeds or fails fatally, for roughly ten seconds.
+//
+// Only tokio's clock is used here, so the retry behavior can be exercised
+// under tokio::time::pause() with any stand-in for the driver.
+async fn wait_for_scrape_result<'a, D: ?Sized, T, Fut, F>(
+    driver: &'a D,
     delay: Duration,
     f: F,
 ) -> Result<T, ScrapeError>
 where
     Fut: Future<Output = Result<T, ScrapeError>>,
-    F: Fn(&'a WebDriver) -> Fut,
+    F: Fn(&'a D) -> Fut,
 {
     let mut last_error: Option<ScrapeError> = None;
     let num_tries = (10.0 / delay.as_secs_f32().ceil()) as i32;
@@ -244,6 +1695,7 @@ where
             Err(ScrapeError::WebDriverError(WebDriverError::StaleElementReference(_))) => {}
             Err(ScrapeError::WebDriverError(x)) => return Err(x.into()),
             Err(e @ ScrapeError::FatalParseError(_)) => return Err(e),
+            Err(ScrapeError::SignInRequired) => return Err(ScrapeError::SignInRequired),
             Err(x) => last_error = Some(x),
         }
         sleep(delay).await;
@@ -254,82 +1706,382 @@ where
     ))
 }
 
-async fn decode_search_result(driver: &WebDriver) -> Result<SearchResult, ScrapeError> {
+// Type the query into the search box and submit it. The box is looked up again
+// on every call, since a reference from an earlier attempt may be stale.
+async fn enter_search_query(driver: &WebDriver, search: &str) -> Result<(), ScrapeError> {
+    let query = match driver.find(By::Name("q")).await {
+        Ok(x) => x,
+        Err(WebDriverError::NoSuchElement(_)) => {
+            return Err(ScrapeError::parse_error("search box not found"));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    query.focus().await?;
+    // Remove any partial query left by an attempt which failed mid-typing.
+    query.clear().await?;
+    query.send_keys(search).await?;
+    query.send_keys("\n").await?;
+    Ok(())
+}
+
+async fn decode_search_result<D: PageDriver>(
+    driver: &D,
+    options: &ClientOptions,
+    detail_concurrency: usize,
+    found: Option<&Sender<LocationInfo>>,
+) -> Result<SearchResult, ScrapeError> {
     // See if we are looking at a single result.
     let current_url = driver.current_url().await?.to_string();
     if current_url.contains("/maps/place") {
-        for x in driver
-            .find_all(By::XPath("//*[starts-with(@role, 'main')]"))
+        return match driver
+            .find_attr("//*[starts-with(@role, 'main')]", "aria-label")
             .await?
         {
-            if let Some(name) = x.attr("aria-label").await? {
-                return Ok(SearchResult::Singular(LocationInfo {
+            Some(Some(name)) => {
+                // The details are nice to have, so don't fail the search if
+                // the panel's layout has changed.
+                let (category, price) = get_place_details(driver).await.unwrap_or_default();
+                Ok(SearchResult::Singular(LocationInfo {
                     name: name,
                     url: current_url,
                     extra: vec![],
-                }));
-            } else {
-                return Err(ScrapeError::parse_error(
-                    "missing expected area-label on main content",
-                ));
+                    price_level: price.as_deref().and_then(parse_price_level),
+                    category,
+                }))
             }
-        }
-        return Err(ScrapeError::parse_error("no main content was found"));
+            Some(None) => Err(ScrapeError::parse_error(
+                "missing expected area-label on main content",
+            )),
+            None => Err(ScrapeError::parse_error("no main content was found")),
+        };
     }
 
-    let no_results: bool = driver
-        .execute(
-            include_str!("injected_scripts/check_empty_search.js"),
-            vec![],
-        )
-        .await?
-        .convert()?;
+    let no_results: bool = run_script(
+        driver,
+        injected_script!("check_empty_search"),
+        vec![serde_json::to_value(&options.not_found_texts)?],
+    )
+    .await?;
     if no_results {
         return Ok(SearchResult::NotFound);
     }
 
     // Look for a list of results.
-    let destinations: Vec<LocationInfo> = driver
-        .execute(include_str!("injected_scripts/parse_search.js"), vec![])
-        .await?
-        .convert()?;
+    let mut destinations: Vec<LocationInfo> = run_script(
+        driver,
+        injected_script!("parse_search"),
+        vec![serde_json::to_value(&options.results_labels)?],
+    )
+    .await?;
 
     if destinations.len() > 0 {
+        fill_details(&mut destinations, detail_concurrency, found).await;
         Ok(SearchResult::Multiple(destinations))
     } else {
         Err(ScrapeError::parse_error("unable to parse search results"))
     }
 }
 
-async fn click_more_reviews_button(driver: &WebDriver) -> Result<usize, ScrapeError> {
+// The most search results which will be followed up on for missing details.
+const MAX_DETAIL_FETCHES: usize = 10;
+
+// Fill in the details of each result from its subtitle lines, sending each
+// one to found (if given) once it's done.
+//
+// Some results in the list lack the subtitle that parse_search.js reads their
+// details from. If concurrency is non-zero, those are filled in from the
+// description on each place's page first. Failures are ignored, leaving the
+// result as it was.
+async fn fill_details(
+    results: &mut [LocationInfo],
+    concurrency: usize,
+    found: Option<&Sender<LocationInfo>>,
+) {
+    let missing: Vec<(usize, String)> = results
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| concurrency > 0 && x.extra.is_empty())
+        .take(MAX_DETAIL_FETCHES)
+        .map(|(i, x)| (i, x.url.clone()))
+        .collect();
+    for (i, x) in results.iter_mut().enumerate() {
+        if !missing.iter().any(|(j, _)| *j == i) {
+            x.fill_details_from_extra();
+            send_found(found, x).await;
+        }
+    }
+    let mut details = stream::iter(missing)
+        .map(|(i, url)| async move { (i, fetch_place_details(&url).await) })
+        .buffer_unordered(concurrency.max(1));
+    while let Some((i, extra)) = details.next().await {
+        if let Some(extra) = extra {
+            results[i].extra = extra;
+        }
+        results[i].fill_details_from_extra();
+        send_found(found, &results[i]).await;
+    }
+}
+
+// The receiver may have gone away, in which case the search still finishes.
+async fn send_found(found: Option<&Sender<LocationInfo>>, info: &LocationInfo) {
+    if let Some(found) = found {
+        found.send(info.clone()).await.ok();
+    }
+}
+
+async fn fetch_place_details(url: &str) -> Option<Vec<String>> {
+    check_pagination_url(url).ok()?;
+    let response = pagination_client().get(url).send().await.ok()?;
+    let html = response.error_for_status().ok()?.text().await.ok()?;
+    let description = meta_description(&html)?;
+    let lines: Vec<String> = description
+        .split(" · ")
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines)
+    }
+}
+
+// Find the content of the page's description <meta> tag, which for places
+// looks like "4.3 ★ · Hotel · 123 Main St".
+fn meta_description(html: &str) -> Option<String> {
+    html.split("<meta ")
+        .skip(1)
+        .filter_map(|x| x.split('>').next())
+        .filter(|tag| {
+            tag.contains("property=\"og:description\"") || tag.contains("itemprop=\"description\"")
+        })
+        .find_map(|tag| {
+            let content = tag.split("content=\"").nth(1)?.split('"').next()?;
+            Some(
+                content
+                    .replace("&quot;", "\"")
+                    .replace("&#39;", "'")
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&amp;", "&"),
+            )
+        })
+}
+
+async fn click_more_reviews_button<D: PageDriver>(driver: &D) -> Result<usize, ScrapeError> {
     // Click the 'more reviews' button even if it's offscreen by using
     // javascript instead of the click() function.
-    let result: Option<usize> = driver
-        .execute(
-            include_str!("injected_scripts/click_more_reviews.js"),
-            vec![],
-        )
-        .await?
-        .convert()?;
+    let result: Option<usize> =
+        run_script(driver, injected_script!("click_more_reviews"), vec![]).await?;
     result.ok_or_else(|| ScrapeError::parse_error("no 'more reviews' button found"))
 }
 
-async fn get_logged_reviews(driver: &WebDriver) -> Result<ReviewResult, ScrapeError> {
-    let result = driver
-        .execute("return window.recordedReviewResponses", vec![])
-        .await?;
-    let results: Vec<(String, String)> = result.convert()?;
+async fn load_more_reviews<D: PageDriver>(driver: &D) -> Result<bool, ScrapeError> {
+    run_script(driver, injected_script!("load_more_reviews"), vec![]).await
+}
+
+async fn has_sign_in_wall<D: PageDriver>(driver: &D) -> Result<bool, ScrapeError> {
+    let current = driver.current_url().await?;
+    if current
+        .host_str()
+        .is_some_and(|x| x.starts_with("accounts."))
+    {
+        return Ok(true);
+    }
+    run_script(driver, injected_script!("check_sign_in_wall"), vec![]).await
+}
+
+async fn get_photo_urls<D: PageDriver>(
+    driver: &D,
+    max_count: usize,
+) -> Result<Vec<String>, ScrapeError> {
+    let result: Vec<String> = run_script(
+        driver,
+        injected_script!("parse_photos"),
+        vec![serde_json::Value::from(max_count)],
+    )
+    .await?;
+    if result.is_empty() {
+        Err(ScrapeError::parse_error("no photos found"))
+    } else {
+        Ok(result)
+    }
+}
+
+async fn get_place_details<D: PageDriver>(
+    driver: &D,
+) -> Result<(Option<String>, Option<String>), ScrapeError> {
+    run_script(driver, injected_script!("parse_place_details"), vec![]).await
+}
+
+async fn get_place_rating<D: PageDriver>(driver: &D) -> Result<PlaceRating, ScrapeError> {
+    let result: Option<(Option<String>, Option<String>)> =
+        run_script(driver, injected_script!("parse_place_rating"), vec![]).await?;
+    let (rating, count) =
+        result.ok_or_else(|| ScrapeError::parse_error("place panel not loaded"))?;
+    // Labels look like "4.3 stars" and "1,204 reviews".
+    let leading_number = |label: &str| -> Option<String> {
+        let number = label.split_whitespace().next()?;
+        Some(number.chars().filter(|c| *c != ',').collect())
+    };
+    Ok(PlaceRating {
+        rating: rating
+            .as_deref()
+            .and_then(leading_number)
+            .and_then(|x| x.parse::<f64>().ok())
+            .filter(|x| (0.0..=5.0).contains(x)),
+        review_count: count
+            .as_deref()
+            .and_then(leading_number)
+            .and_then(|x| x.replace('.', "").parse().ok()),
+    })
+}
+
+// Wait for the recorded review requests, falling back to the rendered review
+// cards if none were captured (e.g. because the reviews were served inline).
+async fn wait_for_reviews<D: PageDriver>(
+    driver: &D,
+    raw_samples: usize,
+) -> Result<ReviewResult, ScrapeError> {
+    let result = match wait_for_scrape_result(driver, Duration::from_secs(1), |driver| {
+        get_logged_reviews(driver, raw_samples)
+    })
+    .await
+    {
+        Err(e @ ScrapeError::TimeoutError(_, _)) => match get_dom_reviews(driver).await {
+            Ok(result) if !result.reviews.is_empty() || result.skipped > 0 => Ok(result),
+            Ok(_) => Err(e),
+            Err(e) => Err(e),
+        },
+        x => x,
+    };
+    result.map_err(|e| e.in_phase(ScrapePhase::Reviews))
+}
+
+async fn get_dom_reviews<D: PageDriver>(driver: &D) -> Result<ReviewResult, ScrapeError> {
+    let cards: Vec<(String, String, String, String, String)> =
+        run_script(driver, injected_script!("parse_review_cards"), vec![]).await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    Ok(parse_dom_reviews(cards, now))
+}
+
+// Cards whose rating or date can't be read (e.g. because the page is in
+// another language) are counted as skipped rather than guessed at.
+fn parse_dom_reviews(
+    cards: Vec<(String, String, String, String, String)>,
+    now: f64,
+) -> ReviewResult {
+    let mut reviews = Vec::new();
+    let mut skipped = 0;
+    for (review_id, author, rating_label, content, date_label) in cards {
+        // Labels look like "4 stars" and "3 months ago".
+        let rating = rating_label
+            .split_whitespace()
+            .next()
+            .and_then(|x| x.parse::<f64>().ok());
+        let (Some(rating), Some(age)) = (rating, relative_age_secs(&date_label)) else {
+            skipped += 1;
+            continue;
+        };
+        reviews.push(Review {
+            review_id,
+            timestamp: now - age,
+            author: repair_mojibake(&author),
+            content: repair_mojibake(&content),
+            rating: rating.clamp(1.0, 5.0),
+            is_local_guide: false,
+            author_review_count: None,
+            raw: None,
+            place: None,
+        });
+    }
+    ReviewResult {
+        next_url: None,
+        reviews,
+        skipped,
+    }
+}
+
+// Find whether the author is a Local Guide, and how many reviews they've
+// written, from labels like "Local Guide · 52 reviews · 140 photos" or "3
+// reviews" somewhere in the author info. Either is left at its default if no
+// such label is found, since the layout of this array isn't stable.
+fn parse_author_stats(author_info: &serde_json::Value) -> (bool, Option<u64>) {
+    let mut labels = Vec::new();
+    let mut stack = vec![(author_info, 0)];
+    while let Some((value, depth)) = stack.pop() {
+        match value {
+            serde_json::Value::String(x) => labels.push(x.as_str()),
+            serde_json::Value::Array(items) if depth < 3 => {
+                stack.extend(items.iter().map(|x| (x, depth + 1)));
+            }
+            _ => {}
+        }
+    }
+    let mut is_local_guide = false;
+    let mut review_count = None;
+    for part in labels.iter().flat_map(|x| x.split('·')) {
+        let part = part.trim();
+        if part.eq_ignore_ascii_case("local guide") {
+            is_local_guide = true;
+        } else if let Some((count, "review" | "reviews")) = part.split_once(' ') {
+            if let Ok(count) = count.replace(',', "").parse() {
+                review_count = Some(count);
+            }
+        }
+    }
+    (is_local_guide, review_count)
+}
+
+// Approximate the age in seconds of a label like "a month ago" or
+// "Edited 3 weeks ago".
+fn relative_age_secs(label: &str) -> Option<f64> {
+    let words: Vec<&str> = label.split_whitespace().collect();
+    let ago = words.iter().position(|x| *x == "ago")?;
+    if ago < 2 {
+        return None;
+    }
+    let count = match words[ago - 2] {
+        "a" | "an" => 1.0,
+        x => x.parse().ok()?,
+    };
+    let unit = match words[ago - 1].trim_end_matches('s') {
+        "second" => 1.0,
+        "minute" => 60.0,
+        "hour" => 3600.0,
+        "day" => 86400.0,
+        "week" => 7.0 * 86400.0,
+        "month" => 30.44 * 86400.0,
+        "year" => 365.25 * 86400.0,
+        _ => return None,
+    };
+    Some(count * unit)
+}
+
+async fn get_logged_reviews<D: PageDriver>(
+    driver: &D,
+    raw_samples: usize,
+) -> Result<ReviewResult, ScrapeError> {
+    let results: Vec<(String, String)> =
+        run_script(driver, RECORDED_REVIEW_RESPONSES, vec![]).await?;
     if results.len() != 0 {
         let mut parsed = Vec::new();
         let mut next_url = None;
+        let mut skipped = 0;
         for (url, result) in results {
-            let parsed_result = parse_logged_reviews(&url, &result)?;
+            let parsed_result =
+                parse_logged_reviews(&url, &result, raw_samples.saturating_sub(parsed.len()))?;
             next_url = parsed_result.next_url;
+            skipped += parsed_result.skipped;
             parsed.extend(parsed_result.reviews);
         }
         return Ok(ReviewResult {
             next_url: next_url,
             reviews: parsed,
+            skipped,
         });
     }
     return Err(ScrapeError::parse_error(
@@ -337,7 +2089,13 @@ async fn get_logged_reviews(driver: &WebDriver) -> Result<ReviewResult, ScrapeEr
     ));
 }
 
-fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, ScrapeError> {
+// Parse a recorded review response, keeping the raw JSON entries of the first
+// raw_samples reviews.
+fn parse_logged_reviews(
+    url: &str,
+    response: &str,
+    raw_samples: usize,
+) -> Result<ReviewResult, ScrapeError> {
     let last_line = response
         .split('\n')
         .last()
@@ -349,6 +2107,7 @@ fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, Scrap
     )?;
     let items = as_array("root list", &results)?;
     let mut reviews = Vec::new();
+    let mut skipped = 0;
     for (i, x) in items.into_iter().enumerate() {
         if x.is_null() || x.is_string() {
             continue;
@@ -367,25 +2126,30 @@ fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, Scrap
                 0,
             )?;
             let data_list_err = format!("review list entry {} has bad data list", i);
+            // The first entry of the data list is a stable review ID, but we
+            // don't want to fail the whole page if it's missing.
+            let review_id = get_array_index(&data_list_err, data_list, 0)
+                .ok()
+                .and_then(|x| x.as_str())
+                .unwrap_or_default()
+                .to_owned();
             let review_metadata = get_array_index(&data_list_err, data_list, 1)?;
             let metadata_err = format!("review list entry {} has bad metadata", i);
             let review_timestamp = as_number(
                 &metadata_err,
                 get_array_index(&metadata_err, review_metadata, 2)?,
             )?;
+            let author_info = get_array_index(
+                &metadata_err,
+                get_array_index(&metadata_err, review_metadata, 4)?,
+                0,
+            )?;
             let review_author = as_string(
                 &metadata_err,
-                get_array_index(
-                    &metadata_err,
-                    get_array_index(
-                        &metadata_err,
-                        get_array_index(&metadata_err, review_metadata, 4)?,
-                        0,
-                    )?,
-                    4,
-                )?,
+                get_array_index(&metadata_err, author_info, 4)?,
             )?
             .to_owned();
+            let (is_local_guide, author_review_count) = parse_author_stats(author_info);
             let review_content = get_array_index(&data_list_err, data_list, 2)?;
             let star_err = format!("review list entry {} invalid stars", i);
             let review_stars = if get_array_index(&star_err, review_content, 0)?.is_null() {
@@ -395,26 +2159,19 @@ fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, Scrap
                 // Alternatively looks like [BUNCH_OF_DATA,8,"8/10","0"].
                 // We want to support reviews that are out of any scale, so we parse the
                 // divisor in the third entry.
-                let divisor: f64 = as_string(
-                    &star_err,
-                    get_array_index(&star_err, get_array_index(&star_err, review_content, 8)?, 2)?,
+                //
+                // Some native reviews also have a null at index 0 and no
+                // index 8 at all. We can't know their rating, so we skip them
+                // rather than failing the whole page, but scale info which is
+                // there and malformed still fails it.
+                let Some(scale_info) = review_content.as_array().and_then(|x| x.get(8)) else {
+                    skipped += 1;
+                    continue;
+                };
+                normalize_rating(
+                    as_number(&star_err, get_array_index(&star_err, scale_info, 1)?)?,
+                    as_string(&star_err, get_array_index(&star_err, scale_info, 2)?)?,
                 )?
-                .split("/")
-                .last()
-                .ok_or_else(|| ScrapeError::parse_error("failed to identify review scale"))?
-                .parse()
-                .map_err(|e| ScrapeError::parse_error(format!("invalid review scale: {}", e)))?;
-
-                ((5.0 / divisor)
-                    * as_number(
-                        &star_err,
-                        get_array_index(
-                            &star_err,
-                            get_array_index(&star_err, review_content, 8)?,
-                            1,
-                        )?,
-                    )?)
-                .clamp(1.0, 5.0)
             } else {
                 as_number(
                     &star_err,
@@ -456,10 +2213,15 @@ fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, Scrap
                 .unwrap_or_default()
             };
             reviews.push(Review {
+                review_id,
                 timestamp: review_timestamp / 1000000.0,
-                author: review_author,
-                content: review_text,
+                author: repair_mojibake(&review_author),
+                content: repair_mojibake(&review_text),
                 rating: review_stars,
+                is_local_guide,
+                author_review_count,
+                raw: (reviews.len() < raw_samples).then(|| x.clone()),
+                place: None,
             });
         }
     }
@@ -485,9 +2247,97 @@ fn parse_logged_reviews(url: &str, response: &str) -> Result<ReviewResult, Scrap
     Ok(ReviewResult {
         next_url: next_url,
         reviews: reviews,
+        skipped,
     })
 }
 
+// Undo UTF-8 text having been decoded as Windows-1252 somewhere upstream,
+// which turns e.g. "–" into "â€“". Text is only changed if every character
+// maps back to a single byte and those bytes form valid UTF-8, which real
+// Latin-1 text (like "café") essentially never does.
+fn repair_mojibake(text: &str) -> String {
+    const CP1252_HIGH: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž',
+        '\u{8f}', '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}',
+        'ž', 'Ÿ',
+    ];
+    if text.is_ascii() {
+        return text.to_owned();
+    }
+    let bytes: Option<Vec<u8>> = text
+        .chars()
+        .map(|c| match c as u32 {
+            x @ (0..=0x7f | 0xa0..=0xff) => Some(x as u8),
+            _ => CP1252_HIGH
+                .iter()
+                .position(|x| *x == c)
+                .map(|i| 0x80 + i as u8),
+        })
+        .collect();
+    match bytes.map(String::from_utf8) {
+        Some(Ok(repaired)) => repaired,
+        _ => text.to_owned(),
+    }
+}
+
+// Rescale a rating like the 8 in "8/10" to the range [1, 5].
+fn normalize_rating(raw: f64, scale_str: &str) -> Result<f64, ScrapeError> {
+    let divisor: f64 = scale_str
+        .rsplit_once('/')
+        .ok_or_else(|| ScrapeError::parse_error("failed to identify review scale"))?
+        .1
+        .trim()
+        .parse()
+        .map_err(|e| ScrapeError::parse_error(format!("invalid review scale: {}", e)))?;
+    if !divisor.is_finite() || divisor <= 0.0 {
+        return Err(ScrapeError::parse_error(format!(
+            "invalid review scale: {}",
+            scale_str
+        )));
+    }
+    if !raw.is_finite() {
+        return Err(ScrapeError::parse_error(format!(
+            "invalid review rating: {}",
+            raw
+        )));
+    }
+    Ok(((5.0 / divisor) * raw).clamp(1.0, 5.0))
+}
+
+fn place_url_coordinates(url: &str) -> Option<(f64, f64)> {
+    let parse_float = |x: &str| -> Option<f64> {
+        let end = x
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+            .unwrap_or(x.len());
+        x[..end].parse().ok()
+    };
+    let valid =
+        |(lat, lon): (f64, f64)| (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon);
+    if let Some(lat_idx) = url.rfind("!3d") {
+        let lat_str = &url[lat_idx + 3..];
+        if let Some(lon_idx) = lat_str.find("!4d") {
+            if let (Some(lat), Some(lon)) =
+                (parse_float(lat_str), parse_float(&lat_str[lon_idx + 3..]))
+            {
+                if valid((lat, lon)) {
+                    return Some((lat, lon));
+                }
+            }
+        }
+    }
+    if let Some(at_idx) = url.find("/@") {
+        let mut parts = url[at_idx + 2..].split(',');
+        if let (Some(lat), Some(lon)) = (parts.next(), parts.next()) {
+            if let (Some(lat), Some(lon)) = (parse_float(lat), parse_float(lon)) {
+                if valid((lat, lon)) {
+                    return Some((lat, lon));
+                }
+            }
+        }
+    }
+    None
+}
+
 fn as_string<D: Display>(err_ctx: D, x: &serde_json::Value) -> Result<&str, ScrapeError> {
     if let serde_json::Value::String(x) = x {
         Ok(x)
@@ -557,3 +2407,304 @@ fn get_array_index<'a, D: Display + ?Sized>(
     }
     Ok(&in_list[i as usize])
 }
+
+// Options for tests, with the defaults that main() would use. The driver URL
+// can be set with REVIEW_PLOT_TEST_DRIVER for tests which need a browser.
+#[cfg(test)]
+pub fn test_client_options() -> ClientOptions {
+    ClientOptions {
+        driver: std::env::var("REVIEW_PLOT_TEST_DRIVER")
+            .unwrap_or_else(|_| "http://localhost:9515".to_owned()),
+        headless: true,
+        search_retries: 0,
+        capture_strategy: CaptureStrategy::Xhr,
+        maps_base_url: "https://www.google.com/maps".to_owned(),
+        chrome_binary: None,
+        chrome_args: vec![],
+        user_data_base: None,
+        persistent_profile: None,
+        detail_concurrency: 0,
+        strict_location: false,
+        more_reviews_clicks: 1,
+        results_labels: DEFAULT_RESULTS_LABELS
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+        not_found_texts: DEFAULT_NOT_FOUND_TEXTS
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Needs a running chromedriver and access to Google Maps.
+    #[tokio::test]
+    #[ignore]
+    async fn reconnected_client_can_search() {
+        let mut client = Client::new(&test_client_options()).await.unwrap();
+        client.reconnect().await.unwrap();
+        let location = GeoLocation {
+            latitude: 37.7749,
+            longitude: -122.4194,
+            accuracy: 100.0,
+            altitude: None,
+            altitude_accuracy: None,
+        };
+        let result = client.search("coffee", &location).await;
+        client.close().await.unwrap();
+        assert!(matches!(
+            result.unwrap(),
+            SearchResult::Singular(_) | SearchResult::Multiple(_)
+        ));
+    }
+
+    #[test]
+    fn normalize_rating_rescales_to_five_stars() {
+        assert_eq!(normalize_rating(5.0, "5/5").unwrap(), 5.0);
+        assert_eq!(normalize_rating(4.0, "4/5").unwrap(), 4.0);
+        assert_eq!(normalize_rating(8.0, "8/10").unwrap(), 4.0);
+        assert_eq!(normalize_rating(7.0, " 7 / 10 ").unwrap(), 3.5);
+        // Ratings are kept within Google's own range.
+        assert_eq!(normalize_rating(0.0, "0/10").unwrap(), 1.0);
+        assert_eq!(normalize_rating(12.0, "12/10").unwrap(), 5.0);
+    }
+
+    #[test]
+    fn normalize_rating_rejects_bad_scales() {
+        for scale in ["", "8", "8/", "8/ten", "8/0", "8/-5", "8/NaN", "8/inf"] {
+            assert!(
+                matches!(
+                    normalize_rating(8.0, scale),
+                    Err(ScrapeError::ParseError(_))
+                ),
+                "{:?} should be rejected",
+                scale
+            );
+        }
+        assert!(normalize_rating(f64::NAN, "8/10").is_err());
+    }
+
+    const REVIEWS_URL: &str =
+        "https://www.google.com/maps/rpc/listugcposts?pb=!1m2!1s0x1%3A0x2!2s!5m2!1sq1";
+
+    #[test]
+    fn only_google_hosts_are_trusted() {
+        for host in [
+            "google.com",
+            "www.google.com",
+            "maps.google.de",
+            "www.google.co.uk",
+            "www.google.com.au",
+        ] {
+            assert!(is_google_host(host), "{}", host);
+        }
+        for host in [
+            "google.evil.com",
+            "evilgoogle.com",
+            "google.com.xyz",
+            "google.xyz",
+            "google.com.evil.com",
+            "maps.google.co.uk.evil.com",
+            "google",
+        ] {
+            assert!(!is_google_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn pagination_urls_must_be_https_google() {
+        assert!(check_pagination_url("https://www.google.com/maps/rpc/listugcposts?pb=x").is_ok());
+        for url in [
+            "http://www.google.com/maps/rpc/listugcposts?pb=x",
+            "https://google.evil.com/maps/rpc/listugcposts?pb=x",
+            "https://evilgoogle.com/maps/rpc/listugcposts?pb=x",
+            "not a url",
+        ] {
+            assert!(check_pagination_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn redirects_stay_on_google() {
+        let google = Url::parse("https://www.google.com/maps").unwrap();
+        assert!(check_redirect(&google, 0).is_ok());
+        assert!(check_redirect(&google, 3).is_err());
+        let evil = Url::parse("https://google.com.xyz/maps").unwrap();
+        assert!(check_redirect(&evil, 0).is_err());
+    }
+
+    #[derive(Default)]
+    struct RecordingDevTools {
+        commands: Mutex<Vec<String>>,
+    }
+
+    impl DevTools for RecordingDevTools {
+        async fn execute_cdp(&self, command: &str) -> WebDriverResult<serde_json::Value> {
+            self.commands.lock().unwrap().push(command.to_owned());
+            Ok(serde_json::Value::Null)
+        }
+
+        async fn execute_cdp_with_params(
+            &self,
+            command: &str,
+            params: serde_json::Value,
+        ) -> WebDriverResult<serde_json::Value> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", command, params));
+            Ok(serde_json::Value::Null)
+        }
+    }
+
+    #[tokio::test]
+    async fn location_is_cleared_when_none_is_given() {
+        let dev_tools = RecordingDevTools::default();
+        let location = GeoLocation {
+            latitude: 1.0,
+            longitude: 2.0,
+            accuracy: 100.0,
+            altitude: None,
+            altitude_accuracy: None,
+        };
+        apply_location(&dev_tools, Some(&location)).await.unwrap();
+        apply_location(&dev_tools, None).await.unwrap();
+        assert_eq!(
+            *dev_tools.commands.lock().unwrap(),
+            vec![
+                r#"Emulation.setGeolocationOverride {"accuracy":100.0,"latitude":1.0,"longitude":2.0}"#,
+                "Emulation.clearGeolocationOverride",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreadable_review_cards_are_skipped() {
+        let card = |id: &str, rating: &str, date: &str| {
+            (
+                id.to_owned(),
+                "Author".to_owned(),
+                rating.to_owned(),
+                "Content".to_owned(),
+                date.to_owned(),
+            )
+        };
+        let result = parse_dom_reviews(
+            vec![
+                card("a", "4 stars", "2 days ago"),
+                card("b", "five stars", "2 days ago"),
+                card("c", "3 stars", "hace 2 días"),
+            ],
+            1000000.0,
+        );
+        assert_eq!(result.reviews.len(), 1);
+        assert_eq!(result.reviews[0].review_id, "a");
+        assert_eq!(result.reviews[0].timestamp, 1000000.0 - 2.0 * 86400.0);
+        assert_eq!(result.skipped, 2);
+    }
+
+    #[test]
+    fn reviews_without_scale_are_skipped() {
+        let response = include_str!("testdata/reviews_missing_scale.json");
+        let result = parse_logged_reviews(REVIEWS_URL, response, 0).unwrap();
+        let ratings: Vec<_> = result
+            .reviews
+            .iter()
+            .map(|x| (x.review_id.as_str(), x.rating))
+            .collect();
+        assert_eq!(ratings, vec![("native", 4.0), ("other_site", 4.5)]);
+        assert_eq!(result.skipped, 1);
+        assert_eq!(result.next_url, None);
+    }
+
+    #[test]
+    fn malformed_scale_fails_the_page() {
+        let response =
+            include_str!("testdata/reviews_missing_scale.json").replace("\"9/10\"", "\"9/0\"");
+        assert!(parse_logged_reviews(REVIEWS_URL, &response, 0).is_err());
+    }
+
+    #[test]
+    fn author_stats_from_fixture() {
+        let fixture: serde_json::Value =
+            serde_json::from_str(include_str!("testdata/author_info.json")).unwrap();
+        assert_eq!(
+            parse_author_stats(&fixture["local_guide"]),
+            (true, Some(1204))
+        );
+        assert_eq!(parse_author_stats(&fixture["reviewer"]), (false, Some(3)));
+        assert_eq!(parse_author_stats(&fixture["no_stats"]), (false, None));
+    }
+
+    #[test]
+    fn valid_text_is_not_repaired() {
+        for text in [
+            "plain ascii",
+            "Café Zoë",
+            "$30–50 per person",
+            "東京のラーメン屋",
+            "Great food 😀👍",
+            "Â is a letter too",
+        ] {
+            assert_eq!(repair_mojibake(text), text);
+        }
+    }
+
+    #[test]
+    fn windows_1252_mojibake_is_repaired() {
+        assert_eq!(repair_mojibake("$30â€“50"), "$30–50");
+        assert_eq!(repair_mojibake("cafÃ© â€œbestâ€\u{9d}"), "café “best”");
+        assert_eq!(repair_mojibake("æ—¥æœ¬"), "日本");
+        assert_eq!(repair_mojibake("ðŸ˜€"), "😀");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn parse_errors_are_retried_until_timeout() {
+        let tries = AtomicUsize::new(0);
+        let start = Instant::now();
+        let result: Result<(), _> =
+            wait_for_scrape_result(&(), Duration::from_secs(1), |_| async {
+                tries.fetch_add(1, Ordering::SeqCst);
+                Err(ScrapeError::parse_error("not loaded yet"))
+            })
+            .await;
+        assert!(matches!(result, Err(ScrapeError::TimeoutError(_, _))));
+        assert_eq!(tries.load(Ordering::SeqCst), 10);
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_stop_at_first_success() {
+        let tries = AtomicUsize::new(0);
+        let start = Instant::now();
+        let result = wait_for_scrape_result(&(), Duration::from_secs(1), |_| async {
+            if tries.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(ScrapeError::parse_error("not loaded yet"))
+            } else {
+                Ok(3)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn fatal_errors_are_not_retried() {
+        let tries = AtomicUsize::new(0);
+        let start = Instant::now();
+        let result: Result<(), _> =
+            wait_for_scrape_result(&(), Duration::from_secs(1), |_| async {
+                tries.fetch_add(1, Ordering::SeqCst);
+                Err(ScrapeError::fatal_parse_error("unexpected layout"))
+            })
+            .await;
+        assert!(matches!(result, Err(ScrapeError::FatalParseError(_))));
+        assert_eq!(tries.load(Ordering::SeqCst), 1);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
@@ -3,82 +3,376 @@ use std::{
     error::Error,
     fmt::Display,
     future::Future,
-    mem::{swap, take},
+    mem::take,
     ops::{Deref, DerefMut},
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
-use super::client::Client;
+use super::client::{Client, ClientOptions, Scraper};
+use futures::{future::join_all, stream, StreamExt};
 use thirtyfour::error::WebDriverResult;
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+// Timestamps use tokio's clock so that tests can control it with
+// tokio::time::pause().
+use tokio::{
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        watch,
+    },
+    time::{sleep, Instant},
+};
 
 #[derive(Debug)]
 pub enum PoolError {
     PoolClosed,
+    Overloaded,
+    // Scraping is paused for this much longer after Google blocked us.
+    CoolingDown(Duration),
+    // The pool is still starting its objects.
+    Warming,
+}
+
+impl PoolError {
+    // How long a caller should wait before trying again.
+    pub fn retry_after(&self) -> Duration {
+        match self {
+            PoolError::PoolClosed => Duration::from_secs(30),
+            PoolError::Overloaded | PoolError::Warming => Duration::from_secs(5),
+            PoolError::CoolingDown(remaining) => {
+                Duration::from_secs(remaining.as_secs_f64().ceil().max(1.0) as u64)
+            }
+        }
+    }
 }
 
 impl Display for PoolError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PoolError::PoolClosed => write!(f, "client pool is closed"),
+            PoolError::Overloaded => write!(f, "too many requests are waiting for a client"),
+            PoolError::CoolingDown(remaining) => write!(
+                f,
+                "scraping is paused for {:.0}s after Google blocked a request",
+                remaining.as_secs_f64().ceil()
+            ),
+            PoolError::Warming => write!(f, "the client pool is still starting"),
         }
     }
 }
 
 impl Error for PoolError {}
 
-pub async fn new_client_pool(
+// Start a pool of browsers, passing each one through wrap (e.g. to record its
+// results) once they have all started.
+pub async fn new_client_pool<S, F: Fn(Client) -> S>(
     capacity: usize,
-    driver: &str,
-    headless: bool,
-) -> WebDriverResult<ObjectPool<Client>> {
+    concurrency: usize,
+    options: &ClientOptions,
+    wrap: F,
+) -> WebDriverResult<ObjectPool<S>> {
+    let results: Vec<WebDriverResult<Client>> = stream::iter(0..capacity)
+        .map(|_| Client::new(options))
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
     let mut objs = Vec::new();
-    for _ in 0..capacity {
-        let obj = Client::new(driver, headless).await?;
-        objs.push(obj);
+    let mut first_err = None;
+    for result in results {
+        match result {
+            Ok(obj) => objs.push(obj),
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    if let Some(e) = first_err {
+        // Don't leak the browsers that did start successfully.
+        for obj in objs {
+            obj.close().await.ok();
+        }
+        return Err(e);
     }
-    Ok(ObjectPool {
-        inner: Arc::new(Mutex::new(ObjectPoolInner {
-            closed: false,
-            capacity: capacity,
-            waiting: VecDeque::new(),
-            free: objs,
-        })),
-    })
+    Ok(ObjectPool::new(objs.into_iter().map(wrap).collect()))
 }
 
+// Like new_client_pool, but return an empty pool right away and add browsers
+// to it as they start. Browsers which fail to start are skipped, and the pool
+// is closed if none of them start.
+pub fn new_warming_client_pool<S, F>(
+    capacity: usize,
+    concurrency: usize,
+    options: &ClientOptions,
+    wrap: F,
+) -> ObjectPool<S>
+where
+    S: Scraper + Send + 'static,
+    F: Fn(Client) -> S + Send + 'static,
+{
+    let pool = ObjectPool::warming();
+    let local_pool = pool.clone();
+    let options = options.clone();
+    tokio::spawn(async move {
+        let mut results = stream::iter(0..capacity)
+            .map(|_| Client::new(&options))
+            .buffer_unordered(concurrency.max(1));
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(client) => {
+                    if let Err(obj) = local_pool.add(wrap(client)) {
+                        // The server stopped before the browser started.
+                        obj.close().await.ok();
+                    }
+                }
+                Err(e) => println!("error starting client: {}", e),
+            }
+        }
+        let capacity = local_pool.finish_warming();
+        println!("client pool warmed up with {} clients", capacity);
+        if capacity == 0 {
+            local_pool.close(|client| client.close()).await.ok();
+        }
+    });
+    pool
+}
+
+// Periodically replace clients which have sat unused in the pool for longer
+// than max_idle, so that long-idle browsers don't accumulate stale state.
+pub fn spawn_idle_recycler<S: Scraper>(pool: ObjectPool<S>, max_idle: Duration) {
+    let check_interval = (max_idle / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+    tokio::spawn(async move {
+        while !pool.is_closed() {
+            sleep(check_interval).await;
+            for mut handle in pool.take_idle(max_idle) {
+                if let Err(e) = handle.reconnect().await {
+                    println!("error recreating idle client: {}", e);
+                }
+            }
+        }
+    });
+}
+
+// Close and recreate every client in the pool, waiting for busy clients to be
+// returned first. Clients which fail to be recreated are kept as-is.
+//
+// Returns the number of clients which were recreated.
+pub async fn recycle_clients<S: Scraper>(pool: &ObjectPool<S>) -> Result<usize, PoolError> {
+    let handles = pool.checkout_all().await?;
+    let results = join_all(handles.into_iter().map(|mut handle| async move {
+        match handle.reconnect().await {
+            Ok(()) => true,
+            Err(e) => {
+                println!("error recreating client: {}", e);
+                false
+            }
+        }
+    }))
+    .await;
+    Ok(results.into_iter().filter(|x| *x).count())
+}
+
+// A fixed set of objects shared between tasks. The lifecycle guarantees are:
+//
+//  - get() hands out free objects immediately, and otherwise queues callers
+//    in FIFO order, even when there are more waiters than objects.
+//  - A waiter which is dropped before receiving an object gives up its place
+//    in the queue, and any object already sent to it goes back to the pool.
+//  - close() closes free objects right away and waits for checked-out objects
+//    to be returned before closing them too.
+//  - After close(), get() fails with PoolError::PoolClosed, as does any get()
+//    which was still waiting.
+//  - If a maximum number of waiters is set, get() fails right away with
+//    PoolError::Overloaded rather than joining a full queue.
+//  - While a cooldown started by PoolHandle::start_cooldown() is running,
+//    get() fails right away with PoolError::CoolingDown.
+//  - A pool made with warming() starts empty and grows with add() until
+//    finish_warming(). In the meantime, get() either queues callers like an
+//    exhausted pool, or fails with PoolError::Warming if set to reject them.
 pub struct ObjectPool<T> {
+    // Only one caller may check out the whole pool at once, or else two such
+    // callers could each hold part of the pool and wait on each other.
+    checkout_all_lock: Arc<tokio::sync::Mutex<()>>,
     inner: Arc<Mutex<ObjectPoolInner<T>>>,
 }
 
 impl<T> Clone for ObjectPool<T> {
     fn clone(&self) -> Self {
         Self {
+            checkout_all_lock: self.checkout_all_lock.clone(),
             inner: self.inner.clone(),
         }
     }
 }
 
 impl<T> ObjectPool<T> {
+    pub fn new(objs: Vec<T>) -> Self {
+        ObjectPool {
+            checkout_all_lock: Default::default(),
+            inner: Arc::new(Mutex::new(ObjectPoolInner {
+                closed: false,
+                closed_signal: watch::channel(false).0,
+                warming: false,
+                reject_while_warming: false,
+                capacity: objs.len(),
+                min_interval: Duration::ZERO,
+                max_waiting: None,
+                block_cooldown: Duration::ZERO,
+                cooldown_until: None,
+                waiting: VecDeque::new(),
+                quarantined: Vec::new(),
+                free: objs.into_iter().map(|x| (x, Instant::now())).collect(),
+            })),
+        }
+    }
+
+    // Create an empty pool which is filled with add() as objects become
+    // ready.
+    pub fn warming() -> Self {
+        let pool = Self::new(Vec::new());
+        pool.inner.lock().unwrap().warming = true;
+        pool
+    }
+
+    // Add a new object to the pool, handing it to a waiter if there is one.
+    // If the pool is closed, the object is given back to be closed.
+    pub fn add(&self, obj: T) -> Result<(), T> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return Err(obj);
+        }
+        inner.capacity += 1;
+        inner.return_object(obj);
+        Ok(())
+    }
+
+    // Mark a warming pool as ready, returning its final capacity.
+    pub fn finish_warming(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.warming = false;
+        inner.capacity
+    }
+
+    pub fn is_warming(&self) -> bool {
+        self.inner.lock().unwrap().warming
+    }
+
+    // Fail get() with PoolError::Warming while the pool is warming, rather
+    // than queueing callers until objects are added.
+    pub fn set_reject_while_warming(&self, reject: bool) {
+        self.inner.lock().unwrap().reject_while_warming = reject;
+    }
+
+    // Set the minimum time between an object being returned to the pool and
+    // it being checked out again.
+    pub fn set_min_interval(&self, min_interval: Duration) {
+        self.inner.lock().unwrap().min_interval = min_interval;
+    }
+
+    // Limit how many callers of get() may wait for an object at once.
+    pub fn set_max_waiting(&self, max_waiting: Option<usize>) {
+        self.inner.lock().unwrap().max_waiting = max_waiting;
+    }
+
+    // Set how long get() should refuse new callers after a cooldown is
+    // started. A zero duration disables cooldowns.
+    pub fn set_block_cooldown(&self, cooldown: Duration) {
+        self.inner.lock().unwrap().block_cooldown = cooldown;
+    }
+
     pub async fn get(&self) -> Result<PoolHandle<T>, PoolError> {
+        let (handle, last_used, min_interval) = self.get_with_last_used(true).await?;
+        let wait = min_interval.saturating_sub(last_used.elapsed());
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+        Ok(handle)
+    }
+
+    async fn get_with_last_used(
+        &self,
+        throttle: bool,
+    ) -> Result<(PoolHandle<T>, Instant, Duration), PoolError> {
         let (tx, rx) = channel(1);
         let tx_arc = Arc::new(tx);
+        let min_interval;
+        let closed;
         {
             let mut inner = self.inner.lock().unwrap();
             if inner.closed {
                 return Err(PoolError::PoolClosed);
             }
-            if let Some(obj) = inner.free.pop() {
+            min_interval = inner.min_interval;
+            if let Some(until) = inner.cooldown_until.filter(|_| throttle) {
+                let remaining = until.saturating_duration_since(Instant::now());
+                if !remaining.is_zero() {
+                    return Err(PoolError::CoolingDown(remaining));
+                }
+            }
+            if throttle && inner.warming && inner.reject_while_warming {
+                return Err(PoolError::Warming);
+            }
+            if let Some((obj, last_used)) = inner.free.pop() {
                 drop(inner);
-                return Ok(PoolHandle {
+                let handle = PoolHandle {
                     pool_inner: self.inner.clone(),
                     obj: Some(obj),
-                });
+                };
+                return Ok((handle, last_used, min_interval));
+            }
+            if throttle && inner.max_waiting.is_some_and(|x| inner.waiting.len() >= x) {
+                return Err(PoolError::Overloaded);
             }
             inner.waiting.push_back(tx_arc.clone());
+            closed = inner.closed_signal.subscribe();
+        }
+        let mut waiter = PoolWaiter::<T>::new(self.inner.clone(), tx_arc, rx, closed);
+        let handle = waiter.recv().await?;
+        // Objects are handed to waiters as soon as they are returned.
+        Ok((handle, Instant::now(), min_interval))
+    }
+
+    // Wait until every object in the pool can be checked out at once.
+    pub async fn checkout_all(&self) -> Result<Vec<PoolHandle<T>>, PoolError> {
+        let _guard = self.checkout_all_lock.lock().await;
+        let capacity = self.inner.lock().unwrap().capacity;
+        let mut handles = Vec::with_capacity(capacity);
+        for _ in 0..capacity {
+            // Callers of checkout_all replace or close the objects rather than
+            // using them, so there's no need to respect min_interval, the
+            // limit on waiters, or a cooldown.
+            handles.push(self.get_with_last_used(false).await?.0);
         }
-        let mut waiter = PoolWaiter::<T>::new(self.inner.clone(), tx_arc, rx);
-        waiter.recv().await
+        Ok(handles)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
+    // The number of objects which are not currently checked out.
+    pub fn available(&self) -> usize {
+        self.inner.lock().unwrap().free.len()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().unwrap().closed
+    }
+
+    // Check out every free object which has been unused for at least max_idle.
+    pub fn take_idle(&self, max_idle: Duration) -> Vec<PoolHandle<T>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.closed {
+            return Vec::new();
+        }
+        let (idle, active) = take(&mut inner.free)
+            .into_iter()
+            .partition(|(_, last_used)| last_used.elapsed() >= max_idle);
+        inner.free = active;
+        idle.into_iter()
+            .map(|(obj, _): (T, Instant)| PoolHandle {
+                pool_inner: self.inner.clone(),
+                obj: Some(obj),
+            })
+            .collect()
     }
 
     pub async fn close<F, Fut, E: Error>(&self, f: F) -> Result<(), E>
@@ -96,8 +390,11 @@ impl<T> ObjectPool<T> {
                 return Ok(());
             }
             inner.closed = true;
-            swap(&mut free, &mut inner.free);
+            // Wake up the callers of get() which were still waiting.
+            inner.closed_signal.send_replace(true);
+            free.extend(take(&mut inner.free).into_iter().map(|(obj, _)| obj));
             remaining = inner.capacity - free.len();
+            free.extend(take(&mut inner.quarantined));
             inner.waiting.clear();
             (tx, rx) = channel(remaining.max(1));
             for _ in 0..remaining {
@@ -114,16 +411,54 @@ impl<T> ObjectPool<T> {
     }
 }
 
+// Exclusive access to one pooled object. The pool never hands the same
+// object to two holders at once: an object is either in the free list, in
+// flight to exactly one waiter, or owned by exactly one handle, and it only
+// becomes available again when that handle is dropped.
 pub struct PoolHandle<T> {
     pool_inner: Arc<Mutex<ObjectPoolInner<T>>>,
     obj: Option<T>,
 }
 
-impl<T> Drop for PoolHandle<T> {
-    fn drop(&mut self) {
+impl<T> PoolHandle<T> {
+    // Permanently remove the object from circulation, shrinking the pool. The
+    // object is kept alive until the pool is closed. The last object in
+    // circulation is returned as usual instead, so that get() can't wait
+    // forever. Returns whether the object was quarantined.
+    pub fn quarantine(mut self) -> bool {
         let obj = take(&mut self.obj).unwrap();
         let mut inner = self.pool_inner.lock().unwrap();
-        inner.return_object(obj);
+        if inner.capacity <= 1 {
+            inner.return_object(obj);
+            return false;
+        }
+        inner.capacity -= 1;
+        inner.quarantined.push(obj);
+        true
+    }
+
+    // Pause new checkouts from the pool for the configured cooldown, e.g.
+    // because this object was just blocked, so that other objects don't keep
+    // making the block worse.
+    pub fn start_cooldown(&self) {
+        let mut inner = self.pool_inner.lock().unwrap();
+        if inner.block_cooldown.is_zero() {
+            return;
+        }
+        println!(
+            "pausing scrapes for {:?} after being blocked",
+            inner.block_cooldown
+        );
+        inner.cooldown_until = Some(Instant::now() + inner.block_cooldown);
+    }
+}
+
+impl<T> Drop for PoolHandle<T> {
+    fn drop(&mut self) {
+        if let Some(obj) = take(&mut self.obj) {
+            let mut inner = self.pool_inner.lock().unwrap();
+            inner.return_object(obj);
+        }
     }
 }
 
@@ -145,25 +480,33 @@ struct PoolWaiter<T> {
     pool: Arc<Mutex<ObjectPoolInner<T>>>,
     tx: Arc<Sender<T>>,
     rx: Option<Receiver<T>>,
+    closed: watch::Receiver<bool>,
 }
 
 impl<T> PoolWaiter<T> {
-    pub fn new(pool: Arc<Mutex<ObjectPoolInner<T>>>, tx: Arc<Sender<T>>, rx: Receiver<T>) -> Self {
+    pub fn new(
+        pool: Arc<Mutex<ObjectPoolInner<T>>>,
+        tx: Arc<Sender<T>>,
+        rx: Receiver<T>,
+        closed: watch::Receiver<bool>,
+    ) -> Self {
         PoolWaiter {
             pool: pool,
             tx: tx,
             rx: Some(rx),
+            closed,
         }
     }
 
     pub async fn recv(&mut self) -> Result<PoolHandle<T>, PoolError> {
-        let obj = self
-            .rx
-            .as_mut()
-            .unwrap()
-            .recv()
-            .await
-            .ok_or(PoolError::PoolClosed)?;
+        let rx = self.rx.as_mut().unwrap();
+        // An object sent just before the pool closed still belongs to us.
+        let obj = tokio::select! {
+            biased;
+            obj = rx.recv() => obj,
+            _ = self.closed.wait_for(|closed| *closed) => None,
+        }
+        .ok_or(PoolError::PoolClosed)?;
         self.rx = None;
         Ok(PoolHandle {
             pool_inner: self.pool.clone(),
@@ -198,20 +541,218 @@ impl<T> Drop for PoolWaiter<T> {
 
 struct ObjectPoolInner<T> {
     closed: bool,
+    closed_signal: watch::Sender<bool>,
+    warming: bool,
+    reject_while_warming: bool,
     capacity: usize,
+    min_interval: Duration,
+    max_waiting: Option<usize>,
+    block_cooldown: Duration,
+    cooldown_until: Option<Instant>,
     waiting: VecDeque<Arc<Sender<T>>>,
-    free: Vec<T>,
+    free: Vec<(T, Instant)>,
+    quarantined: Vec<T>,
 }
 
 impl<T> ObjectPoolInner<T> {
     pub fn return_object(&mut self, obj: T) {
+        // Every returned object was checked out, so the free list can't
+        // already hold the whole pool.
+        debug_assert!(
+            self.free.len() < self.capacity,
+            "object returned to a pool with nothing checked out"
+        );
         if let Some(waiting) = self.waiting.pop_front() {
             // The buffer should never be full, and the
             // other side of the channel won't be dropped
             // unless it's removed from the waiting queue.
             waiting.try_send(obj).unwrap();
         } else {
-            self.free.push(obj);
+            self.free.push((obj, Instant::now()));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn close_pool(pool: &ObjectPool<u32>) {
+        pool.close(|_| async { Ok::<_, PoolError>(()) })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn dropped_handle_returns_object() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        assert_eq!(*handle, 1);
+        assert_eq!(pool.available(), 0);
+        drop(handle);
+        assert_eq!(pool.available(), 1);
+        assert_eq!(*pool.get().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn last_object_is_not_quarantined() {
+        let pool = ObjectPool::new(vec![1, 2]);
+        assert!(pool.get().await.unwrap().quarantine());
+        assert_eq!(pool.capacity(), 1);
+        assert!(!pool.get().await.unwrap().quarantine());
+        assert_eq!(pool.capacity(), 1);
+        assert_eq!(pool.available(), 1);
+        pool.get().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn waiter_woken_by_return() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { *pool.get().await.unwrap() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        drop(handle);
+        assert_eq!(waiter.await.unwrap(), 1);
+        // The waiter dropped its handle, so the object is free again.
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_waiter_leaves_queue() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        let cancelled = tokio::spawn({
+            let pool = pool.clone();
+            async move { *pool.get().await.unwrap() }
+        });
+        tokio::task::yield_now().await;
+        cancelled.abort();
+        assert!(cancelled.await.unwrap_err().is_cancelled());
+        assert!(pool.inner.lock().unwrap().waiting.is_empty());
+        drop(handle);
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn object_sent_to_dropped_waiter_is_freed() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        let mut waiter = Box::pin(pool.get());
+        assert!(futures::poll!(&mut waiter).is_pending());
+        // The object is handed to the waiter before it gets to run.
+        drop(handle);
+        assert_eq!(pool.available(), 0);
+        drop(waiter);
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn object_sent_to_dropped_waiter_goes_to_next_waiter() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        let mut first = Box::pin(pool.get());
+        assert!(futures::poll!(&mut first).is_pending());
+        let second = tokio::spawn({
+            let pool = pool.clone();
+            async move { *pool.get().await.unwrap() }
+        });
+        tokio::task::yield_now().await;
+        drop(handle);
+        drop(first);
+        assert_eq!(second.await.unwrap(), 1);
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[tokio::test]
+    async fn full_queue_is_overloaded() {
+        let pool = ObjectPool::new(vec![1]);
+        pool.set_max_waiting(Some(1));
+        let handle = pool.get().await.unwrap();
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { *pool.get().await.unwrap() }
+        });
+        tokio::task::yield_now().await;
+        assert!(matches!(pool.get().await, Err(PoolError::Overloaded)));
+        drop(handle);
+        assert_eq!(waiter.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn close_wakes_waiters() {
+        let pool = ObjectPool::new(vec![1]);
+        let handle = pool.get().await.unwrap();
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.map(|x| *x) }
+        });
+        tokio::task::yield_now().await;
+        let close = tokio::spawn({
+            let pool = pool.clone();
+            async move { close_pool(&pool).await }
+        });
+        assert!(matches!(waiter.await.unwrap(), Err(PoolError::PoolClosed)));
+        // close() waits for the checked-out object before finishing.
+        tokio::task::yield_now().await;
+        assert!(!close.is_finished());
+        drop(handle);
+        close.await.unwrap();
+        assert!(matches!(pool.get().await, Err(PoolError::PoolClosed)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn objects_are_held_by_one_task_at_a_time() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let in_use: Arc<Vec<AtomicBool>> =
+            Arc::new((0..3).map(|_| AtomicBool::new(false)).collect());
+        let holders = Arc::new(AtomicUsize::new(0));
+        let pool = ObjectPool::new(vec![0, 1, 2]);
+        let tasks: Vec<_> = (0..32)
+            .map(|_| {
+                let (pool, in_use, holders) = (pool.clone(), in_use.clone(), holders.clone());
+                tokio::spawn(async move {
+                    for _ in 0..50 {
+                        let handle = pool.get().await.unwrap();
+                        assert!(!in_use[*handle].swap(true, Ordering::SeqCst));
+                        assert!(holders.fetch_add(1, Ordering::SeqCst) < 3);
+                        tokio::task::yield_now().await;
+                        holders.fetch_sub(1, Ordering::SeqCst);
+                        in_use[*handle].store(false, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(pool.available(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn min_interval_spaces_out_reuse() {
+        let pool = ObjectPool::new(vec![1]);
+        pool.set_min_interval(Duration::from_secs(5));
+
+        // The object counts as used when the pool was created.
+        let start = Instant::now();
+        let handle = pool.get().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+
+        // The interval is measured from when the object was returned.
+        sleep(Duration::from_secs(2)).await;
+        drop(handle);
+        let start = Instant::now();
+        drop(pool.get().await.unwrap());
+        assert_eq!(start.elapsed(), Duration::from_secs(5));
+
+        sleep(Duration::from_secs(10)).await;
+        let start = Instant::now();
+        drop(pool.get().await.unwrap());
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
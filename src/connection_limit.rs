@@ -0,0 +1,71 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+// Caps how many connections each client IP may have open at once, so that one
+// client can't tie up the server by holding hundreds of them.
+pub struct ConnectionLimiter {
+    // Zero means no limit.
+    max_per_ip: usize,
+    open: Arc<Mutex<HashMap<String, usize>>>,
+    rejected: AtomicU64,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize) -> Self {
+        ConnectionLimiter {
+            max_per_ip,
+            open: Default::default(),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    // Count a new connection from the IP, returning None if it already has
+    // the most connections allowed. The connection counts as open until the
+    // returned guard is dropped.
+    pub fn try_open(&self, ip: &str) -> Option<OpenConnection> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(ip.to_owned()).or_default();
+        if self.max_per_ip > 0 && *count >= self.max_per_ip {
+            self.rejected.fetch_add(1, Ordering::SeqCst);
+            return None;
+        }
+        *count += 1;
+        Some(OpenConnection {
+            ip: ip.to_owned(),
+            open: self.open.clone(),
+        })
+    }
+
+    // Render the counters in the Prometheus text exposition format.
+    pub fn prometheus_metrics(&self) -> String {
+        let name = "review_plot_rejected_connections_total";
+        format!(
+            "# HELP {name} Connections closed because their IP had too many open.\n\
+             # TYPE {name} counter\n{name} {}\n",
+            self.rejected.load(Ordering::SeqCst)
+        )
+    }
+}
+
+pub struct OpenConnection {
+    ip: String,
+    open: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.ip) {
+            *count -= 1;
+            // Don't keep an entry for every IP which ever connected.
+            if *count == 0 {
+                open.remove(&self.ip);
+            }
+        }
+    }
+}
@@ -1,9 +1,191 @@
 use hyper::{body, Request};
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
 
 const LOCATION_DATA: &'static [u8] = include_bytes!("data/locations_256.json.gz");
 
+// Locations are stored per /24 block, which typically only narrows a client
+// down to a metro area, so IP-derived positions shouldn't claim to be more
+// accurate than this.
+pub const IP_LOCATION_ACCURACY_M: f64 = 5000.0;
+
+// Locations guessed from a client's language only narrow it down to a country.
+pub const LANGUAGE_LOCATION_ACCURACY_M: f64 = 500_000.0;
+
+// Rough population centers of countries, by ISO 3166 region code.
+const REGION_CENTERS: &[(&str, f64, f64)] = &[
+    ("AE", 24.4, 54.5),
+    ("AR", -34.6, -60.0),
+    ("AT", 47.8, 15.0),
+    ("AU", -33.0, 146.0),
+    ("BE", 50.8, 4.4),
+    ("BR", -20.0, -46.0),
+    ("CA", 45.5, -76.0),
+    ("CH", 47.2, 8.0),
+    ("CL", -33.5, -70.7),
+    ("CN", 32.0, 116.0),
+    ("CO", 5.0, -74.5),
+    ("CZ", 49.8, 15.5),
+    ("DE", 50.9, 9.5),
+    ("DK", 55.8, 11.0),
+    ("EG", 30.0, 31.2),
+    ("ES", 40.0, -3.7),
+    ("FI", 61.5, 24.5),
+    ("FR", 47.0, 2.5),
+    ("GB", 52.5, -1.5),
+    ("GR", 38.5, 23.0),
+    ("HK", 22.3, 114.2),
+    ("HU", 47.4, 19.3),
+    ("ID", -6.5, 108.0),
+    ("IE", 53.3, -7.5),
+    ("IL", 32.0, 34.9),
+    ("IN", 23.0, 79.0),
+    ("IT", 43.0, 12.0),
+    ("JP", 35.5, 137.5),
+    ("KR", 37.0, 127.5),
+    ("MX", 20.5, -100.0),
+    ("MY", 3.5, 101.5),
+    ("NL", 52.2, 5.3),
+    ("NO", 60.0, 10.0),
+    ("NZ", -39.0, 175.0),
+    ("PE", -12.0, -77.0),
+    ("PH", 14.6, 121.0),
+    ("PL", 52.0, 19.5),
+    ("PT", 39.5, -8.5),
+    ("RO", 45.5, 25.0),
+    ("RU", 55.7, 40.0),
+    ("SA", 24.0, 45.0),
+    ("SE", 59.5, 16.0),
+    ("SG", 1.35, 103.8),
+    ("TH", 14.0, 100.5),
+    ("TR", 39.5, 32.0),
+    ("TW", 24.0, 121.0),
+    ("UA", 49.0, 31.0),
+    ("US", 38.0, -90.0),
+    ("VN", 16.0, 106.0),
+    ("ZA", -27.0, 27.0),
+];
+
+// The country to assume for a language tag without a region, for languages
+// which are mostly spoken in one country. Languages like English and Spanish
+// are left out, since any one country would often be wrong.
+const LANGUAGE_REGIONS: &[(&str, &str)] = &[
+    ("cs", "CZ"),
+    ("da", "DK"),
+    ("de", "DE"),
+    ("el", "GR"),
+    ("fi", "FI"),
+    ("fr", "FR"),
+    ("he", "IL"),
+    ("hu", "HU"),
+    ("id", "ID"),
+    ("it", "IT"),
+    ("ja", "JP"),
+    ("ko", "KR"),
+    ("nb", "NO"),
+    ("nl", "NL"),
+    ("nn", "NO"),
+    ("no", "NO"),
+    ("pl", "PL"),
+    ("ro", "RO"),
+    ("ru", "RU"),
+    ("sv", "SE"),
+    ("th", "TH"),
+    ("tr", "TR"),
+    ("uk", "UA"),
+    ("vi", "VN"),
+    ("zh", "CN"),
+];
+
+// Guess a location from an Accept-Language header like "en-GB,en;q=0.9",
+// using the most preferred language tag which implies a country.
+pub fn locate_accept_language(header: &str) -> Option<(f64, f64)> {
+    let mut tags: Vec<(f64, &str)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let tag = parts.next()?.trim();
+            let quality = match parts.find_map(|x| x.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse().ok()?,
+                None => 1.0,
+            };
+            Some((quality, tag))
+        })
+        .filter(|(quality, tag)| *quality > 0.0 && !tag.is_empty() && *tag != "*")
+        .collect();
+    // The sort is stable, so equally preferred tags keep their order.
+    tags.sort_by(|a, b| b.0.total_cmp(&a.0));
+    tags.into_iter()
+        .find_map(|(_, tag)| language_tag_center(tag))
+}
+
+fn language_tag_center(tag: &str) -> Option<(f64, f64)> {
+    let mut subtags = tag.split(['-', '_']);
+    let language = subtags.next()?.to_ascii_lowercase();
+    // The region is the first two-letter subtag after the language, like "GB"
+    // in "en-GB" or "TW" in "zh-Hant-TW".
+    let region = match subtags.find(|x| x.len() == 2 && x.chars().all(|c| c.is_ascii_alphabetic()))
+    {
+        Some(region) => region.to_ascii_uppercase(),
+        None => LANGUAGE_REGIONS
+            .iter()
+            .find(|(x, _)| *x == language)
+            .map(|(_, region)| region.to_string())?,
+    };
+    REGION_CENTERS
+        .iter()
+        .find(|(x, _, _)| *x == region)
+        .map(|(_, lat, lon)| (*lat, *lon))
+}
+
+// Parse a dotted IPv4 address into a number.
+fn parse_ipv4(ip: &str) -> Option<u32> {
+    let parts: Vec<&str> = ip.split(".").collect();
+    if parts.len() != 4 {
+        return None;
+    }
+    let mut result = 0;
+    for part in parts {
+        // Parsing as u8 rejects components which would overflow.
+        result = result * 0x100 + part.parse::<u8>().ok()? as u32;
+    }
+    Some(result)
+}
+
+fn format_ipv4(ip: u32) -> String {
+    let [a, b, c, d] = ip.to_be_bytes();
+    format!("{}.{}.{}.{}", a, b, c, d)
+}
+
+// Parse a block like "1.2.3.0/28", or a single address, into its first and
+// last addresses.
+fn parse_ipv4_block(block: &str) -> Option<(u32, u32)> {
+    let (ip, prefix_len) = match block.split_once('/') {
+        Some((ip, len)) => (ip, len.parse::<u32>().ok().filter(|x| *x <= 32)?),
+        None => (block, 32),
+    };
+    let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+    let start = parse_ipv4(ip)? & mask;
+    Some((start, start | !mask))
+}
+
+fn invalid_data(path: &Path, message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
 pub struct IpLocator {
+    // Blocks from an optional finer-grained DB, as (first, last, lat, lon),
+    // sorted and non-overlapping. These take precedence over locations.
+    blocks: Vec<(u32, u32, f64, f64)>,
+    // The embedded per-/24 locations, sorted by address. An IP gets the
+    // location of the nearest entry.
     locations: Vec<(u32, f64, f64)>,
     num_proxies: usize,
 }
@@ -19,25 +201,64 @@ impl IpLocator {
             serde_json::from_slice(&buf).expect("parse static location data");
         let mut locations = Vec::new();
         for (k, v) in parsed.into_iter() {
-            let parts: Vec<u32> = k.split(".").map(|x| x.parse().expect("parse IP")).collect();
-            locations.push((
-                parts[0] * 0x1000000 + parts[1] * 0x10000 + parts[2] * 0x100 + parts[3],
-                v.0,
-                v.1,
-            ));
+            locations.push((parse_ipv4(&k).expect("parse IP"), v.0, v.1));
         }
+        locations.sort_by_key(|x| x.0);
         println!("loaded IP location DB with {} entries", locations.len());
         IpLocator {
+            blocks: Vec::new(),
             locations,
             num_proxies: num_proxies,
         }
     }
 
+    // Load a finer-grained DB to consult before the embedded one. It has the
+    // same format as the embedded DB, a JSON object mapping IPs to [lat, lon]
+    // (gzipped if the path ends in .gz), except that keys may also be CIDR
+    // blocks like "1.2.3.0/28". IPs outside of every block fall back to the
+    // embedded DB.
+    pub fn load_fine_db(&mut self, path: &Path) -> io::Result<()> {
+        let mut buf = Vec::new();
+        let file = File::open(path)?;
+        if path.extension().is_some_and(|x| x == "gz") {
+            flate2::read::GzDecoder::new(file).read_to_end(&mut buf)?;
+        } else {
+            io::BufReader::new(file).read_to_end(&mut buf)?;
+        }
+        let parsed: HashMap<String, (f64, f64)> =
+            serde_json::from_slice(&buf).map_err(|e| invalid_data(path, e))?;
+        let mut blocks = Vec::with_capacity(parsed.len());
+        for (k, (lat, lon)) in parsed {
+            let (first, last) = parse_ipv4_block(&k)
+                .ok_or_else(|| invalid_data(path, format!("invalid IPv4 block: {:?}", k)))?;
+            blocks.push((first, last, lat, lon));
+        }
+        blocks.sort_by_key(|x| x.0);
+        if let Some(w) = blocks.windows(2).find(|w| w[1].0 <= w[0].1) {
+            return Err(invalid_data(
+                path,
+                format!(
+                    "blocks starting at {} and {} overlap",
+                    format_ipv4(w[0].0),
+                    format_ipv4(w[1].0)
+                ),
+            ));
+        }
+        println!("loaded fine IP location DB with {} blocks", blocks.len());
+        self.blocks = blocks;
+        Ok(())
+    }
+
     pub fn lookup_for_request(
         &self,
         req: &Request<body::Incoming>,
         addr: &str,
     ) -> Option<(f64, f64)> {
+        self.lookup(&self.request_ip(req, addr)?)
+    }
+
+    // Get the client IP for a request, taking trusted proxies into account.
+    pub fn request_ip(&self, req: &Request<body::Incoming>, addr: &str) -> Option<String> {
         if self.num_proxies > 0 {
             if let Some(forwarded) = req.headers().get("x-forwarded-for") {
                 let addrs: Vec<&[u8]> = forwarded.as_bytes().split(|x| *x == b',').collect();
@@ -47,44 +268,27 @@ impl IpLocator {
                 if let Ok(addr) =
                     String::from_utf8(addrs[addrs.len() - self.num_proxies].to_owned())
                 {
-                    return self.lookup(addr.trim());
+                    return Some(addr.trim().to_owned());
                 }
             }
         }
-        self.lookup(addr)
+        Some(addr.to_owned())
     }
 
     pub fn lookup(&self, ip: &str) -> Option<(f64, f64)> {
-        let parts: Vec<&str> = ip.split(".").collect();
-        if parts.len() != 4 {
-            return None;
-        }
-        let parsed: Result<Vec<u32>, _> = parts.iter().map(|x| x.parse()).collect();
-        if let Ok(components) = parsed {
-            if !components.iter().all(|x| *x < 256) {
-                // Avoid overflow
-                return None;
-            }
-            let ip_num = components[0] * 0x1000000
-                + components[1] * 0x10000
-                + components[2] * 0x100
-                + components[3];
-            let mut min_dist: u32 = 0xffffffff;
-            let mut result = (0.0, 0.0);
-            for (cur_ip, lat, lon) in &self.locations {
-                let dist = if *cur_ip > ip_num {
-                    cur_ip - ip_num
-                } else {
-                    ip_num - cur_ip
-                };
-                if dist <= min_dist {
-                    min_dist = dist;
-                    result = (*lat, *lon);
-                }
+        let ip_num = parse_ipv4(ip)?;
+        let i = self.blocks.partition_point(|x| x.0 <= ip_num);
+        if let Some((_, last, lat, lon)) = i.checked_sub(1).map(|i| self.blocks[i]) {
+            if ip_num <= last {
+                return Some((lat, lon));
             }
-            Some(result)
-        } else {
-            None
         }
+        // The nearest entry is on one side or the other of where the IP would
+        // be inserted.
+        let i = self.locations.partition_point(|x| x.0 <= ip_num);
+        self.locations[i.saturating_sub(1)..(i + 1).min(self.locations.len())]
+            .iter()
+            .min_by_key(|(cur_ip, _, _)| cur_ip.abs_diff(ip_num))
+            .map(|(_, lat, lon)| (*lat, *lon))
     }
 }
@@ -1,27 +1,70 @@
-use std::{collections::HashMap, convert::Infallible, error::Error, fmt::Display, str::FromStr};
+use std::{
+    any::type_name,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    error::Error,
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use bytes::Bytes;
-use futures::StreamExt;
-use http::response::Builder;
-use http_body_util::{combinators::BoxBody, Full, StreamBody};
+use futures::{
+    future::Abortable,
+    stream::{self, SplitSink},
+    Future, SinkExt, StreamExt,
+};
+use http::{response::Builder, HeaderMap, HeaderValue, Method, StatusCode};
+use http_body_util::{combinators::BoxBody, BodyExt, Full, Limited, StreamBody};
 use hyper::{
     body::{self, Frame},
+    upgrade::Upgraded,
     Request, Response,
 };
+use hyper_util::rt::TokioIo;
 
-use super::client::{Client, GeoLocation, LocationInfo, ScrapeError, SearchResult};
-use super::client_pool::{ObjectPool, PoolError};
+use super::client::{
+    is_google_host, is_short_link, resolve_short_link, GeoLocation, GoogleSort, LocationInfo,
+    PlaceRating, Review, ReviewIter, ReviewListOptions, ScrapeError, Scraper, SearchResult,
+};
+use super::client_pool::{recycle_clients, ObjectPool, PoolError, PoolHandle};
+use super::connection_limit::ConnectionLimiter;
+use super::geolocate::{locate_accept_language, IpLocator, LANGUAGE_LOCATION_ACCURACY_M};
+use super::output::{OutputEncoder, ReviewEncoding, ReviewFormat, ReviewOrder, TimeFormat};
+use super::review_cache::{CacheStatus, CachedReviews, PageCache, ReviewCache};
+use super::scrape_registry::ScrapeRegistry;
+use super::search_cache::SearchCache;
+use super::stats::{EvenSampler, Interval, RatingDistribution, ReviewSummary};
 use serde::Serialize;
 use serde_json::json;
-use tokio::sync::mpsc::channel;
+use tokio::sync::{
+    mpsc::{channel, Sender},
+    oneshot,
+};
+use tokio::time::Instant;
 use tokio_stream::wrappers::ReceiverStream;
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
 
 #[derive(Debug)]
 pub enum HandlerError {
     ScrapeError(ScrapeError),
     PoolError(PoolError),
     HttpError(http::Error),
-    QueryError(String),
+    QueryError {
+        field: Option<String>,
+        message: String,
+    },
+    Unauthorized(String),
+    Forbidden(String),
+    MethodNotAllowed(Method),
 }
 
 impl Display for HandlerError {
@@ -30,7 +73,33 @@ impl Display for HandlerError {
             HandlerError::ScrapeError(e) => write!(f, "ScrapeError({})", e),
             HandlerError::PoolError(e) => write!(f, "PoolError({})", e),
             HandlerError::HttpError(e) => write!(f, "HttpError({})", e),
-            HandlerError::QueryError(e) => write!(f, "QueryError({})", e),
+            HandlerError::QueryError {
+                field: Some(field),
+                message,
+            } => write!(f, "QueryError({}: {})", field, message),
+            HandlerError::QueryError {
+                field: None,
+                message,
+            } => write!(f, "QueryError({})", message),
+            HandlerError::Unauthorized(e) => write!(f, "Unauthorized({})", e),
+            HandlerError::Forbidden(e) => write!(f, "Forbidden({})", e),
+            HandlerError::MethodNotAllowed(m) => write!(f, "MethodNotAllowed({})", m),
+        }
+    }
+}
+
+impl HandlerError {
+    pub fn query_error<S: Display>(msg: S) -> Self {
+        HandlerError::QueryError {
+            field: None,
+            message: format!("{}", msg),
+        }
+    }
+
+    pub fn field_error<S: Display>(field: &str, msg: S) -> Self {
+        HandlerError::QueryError {
+            field: Some(field.to_owned()),
+            message: format!("{}", msg),
         }
     }
 }
@@ -55,92 +124,2011 @@ impl From<http::Error> for HandlerError {
 
 impl From<url::ParseError> for HandlerError {
     fn from(value: url::ParseError) -> Self {
-        HandlerError::QueryError(format!("failed to parse URL: {}", value))
+        HandlerError::query_error(format!("failed to parse URL: {}", value))
     }
 }
 
 impl Error for HandlerError {}
 
-pub async fn handle_search(
-    pool: ObjectPool<Client>,
+// State shared by all API handlers.
+pub struct HandlerContext {
+    pub pagination_delay: Duration,
+    pub search_cache: SearchCache,
+    pub review_cache: ReviewCache,
+    pub page_cache: Option<Arc<PageCache>>,
+    pub admin_token: Option<String>,
+    pub default_location: Option<(f64, f64)>,
+    pub location_accuracy_floor: f64,
+    pub location_precision: Option<f64>,
+    pub default_accuracy: f64,
+    pub in_flight: InFlightCounter,
+    pub scrapes: ScrapeRegistry,
+    pub connections: ConnectionLimiter,
+    pub place_url_policy: PlaceUrlPolicy,
+    pub release: ClientRelease,
+    pub debug: bool,
+    pub max_total_reviews: Option<u64>,
+    pub max_scrape_duration: Option<Duration>,
+    pub merge_concurrency: usize,
+    pub completeness: CompletenessCheck,
+}
+
+// Restricts which URLs may be scraped for reviews, so that the server can't
+// be used to fetch arbitrary pages.
+pub struct PlaceUrlPolicy {
+    // Accept any URL at all.
+    pub allow_any: bool,
+    // If non-empty, only these place IDs (e.g. 0x808f...:0xb04f... or
+    // ChIJ...) may be scraped.
+    pub place_ids: Vec<String>,
+}
+
+impl PlaceUrlPolicy {
+    fn check(&self, raw_url: &str) -> Result<(), HandlerError> {
+        if self.allow_any {
+            return Ok(());
+        }
+        let forbidden = || HandlerError::Forbidden(format!("URL is not allowed: {}", raw_url));
+        let url = url::Url::parse(raw_url).map_err(|_| forbidden())?;
+        if url.scheme() != "https"
+            || !url.host_str().is_some_and(is_google_host)
+            || !url.path().starts_with("/maps/place/")
+        {
+            return Err(forbidden());
+        }
+        if !self.place_ids.is_empty() {
+            let path = url.path().replace("%3A", ":").replace("%3a", ":");
+            let matches = self.place_ids.iter().any(|id| {
+                path.contains(&format!("!1s{}", id)) || path.contains(&format!("!19s{}", id))
+            });
+            if !matches {
+                return Err(forbidden());
+            }
+        }
+        Ok(())
+    }
+}
+
+// Counts the scrapes which currently hold a pooled client, or which are still
+// streaming results obtained from one.
+#[derive(Default)]
+pub struct InFlightCounter(Arc<AtomicUsize>);
+
+impl InFlightCounter {
+    pub fn get(&self) -> usize {
+        self.0.load(AtomicOrdering::SeqCst)
+    }
+
+    // The count is decremented when the returned guard is dropped, so it
+    // stays accurate if the handler fails or is cancelled.
+    fn start(&self) -> InFlightGuard {
+        self.0.fetch_add(1, AtomicOrdering::SeqCst);
+        InFlightGuard(self.0.clone())
+    }
+}
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, AtomicOrdering::SeqCst);
+    }
+}
+
+// What to do with a client when a scrape is done with it.
+#[derive(Clone)]
+pub struct ClientRelease {
+    // Keep clients whose scrapes failed out of the pool, for debugging.
+    pub debug_browser: bool,
+    pub errors: ErrorRateWindow,
+}
+
+// The fewest scrapes in the window for the error rate to count.
+const MIN_ERROR_RATE_SAMPLES: usize = 5;
+
+// The most recent scrape outcomes that are kept, whatever the window.
+const MAX_ERROR_RATE_SAMPLES: usize = 10000;
+
+// Tracks how many of the scrapes in a recent window of time failed, so that
+// the server can report itself as unhealthy when most of them do. That
+// usually means Google changed its pages or is blocking us.
+#[derive(Clone)]
+pub struct ErrorRateWindow {
+    window: Duration,
+    max_error_rate: f64,
+    outcomes: Arc<Mutex<VecDeque<(Instant, bool)>>>,
+}
+
+impl ErrorRateWindow {
+    pub fn new(window: Duration, max_error_rate: f64) -> Self {
+        Self {
+            window,
+            max_error_rate,
+            outcomes: Default::default(),
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        outcomes.push_back((Instant::now(), failed));
+        if outcomes.len() > MAX_ERROR_RATE_SAMPLES {
+            outcomes.pop_front();
+        }
+    }
+
+    // Get the number of scrapes in the window, and the fraction of them that
+    // failed if there were enough to tell.
+    fn error_rate(&self) -> (usize, Option<f64>) {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        while outcomes
+            .front()
+            .is_some_and(|(time, _)| time.elapsed() > self.window)
+        {
+            outcomes.pop_front();
+        }
+        let count = outcomes.len();
+        let failed = outcomes.iter().filter(|(_, failed)| *failed).count();
+        let rate = (count >= MIN_ERROR_RATE_SAMPLES).then(|| failed as f64 / count as f64);
+        (count, rate)
+    }
+}
+
+// A place with too many reviews is the caller's problem, not ours.
+fn counts_as_failure(e: &ScrapeError) -> bool {
+    !matches!(e.root(), ScrapeError::TooManyReviews(_, _))
+}
+
+// Records whether a review scrape failed when it ends, however it ends. A
+// scrape which is cancelled or whose client hangs up counts as a success.
+struct ScrapeOutcome {
+    errors: ErrorRateWindow,
+    failed: bool,
+}
+
+impl ScrapeOutcome {
+    fn new(errors: &ErrorRateWindow) -> Self {
+        Self {
+            errors: errors.clone(),
+            failed: false,
+        }
+    }
+
+    fn note<T>(&mut self, result: &Result<T, ScrapeError>) {
+        if result.as_ref().is_err_and(counts_as_failure) {
+            self.failed = true;
+        }
+    }
+
+    // Pass a result through, noting whether it failed the scrape.
+    fn check<T>(&mut self, result: Result<T, ScrapeError>) -> Result<T, ScrapeError> {
+        self.note(&result);
+        result
+    }
+}
+
+impl Drop for ScrapeOutcome {
+    fn drop(&mut self) {
+        self.errors.record(self.failed);
+    }
+}
+
+#[derive(Serialize)]
+pub struct HealthStatus {
+    pub ready: bool,
+    // The server is up, but the client pool is still starting browsers.
+    pub warming: bool,
+    pub recent_scrapes: usize,
+    pub error_rate: Option<f64>,
+}
+
+impl HealthStatus {
+    pub fn new<T>(pool: &ObjectPool<T>, context: &HandlerContext) -> Self {
+        let errors = &context.release.errors;
+        let (recent_scrapes, error_rate) = errors.error_rate();
+        let warming = pool.is_warming();
+        Self {
+            ready: !warming && error_rate.map_or(true, |x| x <= errors.max_error_rate),
+            warming,
+            recent_scrapes,
+            error_rate,
+        }
+    }
+
+    // Readiness probes only look at the status code.
+    pub fn status(&self) -> StatusCode {
+        if self.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+    }
+}
+
+fn skipped_reviews_warning(skipped: usize) -> Option<String> {
+    (skipped > 0).then(|| format!("{} reviews could not be parsed and were left out", skipped))
+}
+
+// Flags scrapes which run to the end but get far fewer reviews than the place
+// panel reported, which usually means that Google changed its format in a way
+// that the parser only partly handles.
+#[derive(Clone)]
+pub struct CompletenessCheck {
+    // Warn when less than this fraction of the reported reviews are scraped.
+    min_ratio: f64,
+    incomplete: Arc<AtomicUsize>,
+}
+
+impl CompletenessCheck {
+    // A min_ratio of zero disables the check.
+    pub fn new(min_ratio: f64) -> Self {
+        Self {
+            min_ratio,
+            incomplete: Default::default(),
+        }
+    }
+
+    // Return a warning if the scrape looks incomplete, counting it as such.
+    fn check(&self, total: Option<u64>, scraped: usize) -> Option<String> {
+        let total = total?;
+        if (scraped as f64) >= self.min_ratio * (total as f64) {
+            return None;
+        }
+        self.incomplete.fetch_add(1, AtomicOrdering::SeqCst);
+        println!(
+            "warning: scraped {} of {} reported reviews; the parser may be out of date",
+            scraped, total
+        );
+        Some(format!(
+            "only {} of the place's {} reviews were scraped, so some are likely missing",
+            scraped, total
+        ))
+    }
+
+    pub fn prometheus_metrics(&self) -> String {
+        let name = "review_plot_incomplete_scrapes_total";
+        format!(
+            "# HELP {name} Scrapes which ended with far fewer reviews than the place reported.\n\
+             # TYPE {name} counter\n{name} {}\n",
+            self.incomplete.load(AtomicOrdering::SeqCst)
+        )
+    }
+}
+
+#[derive(Serialize)]
+pub struct RankedLocation {
+    #[serde(flatten)]
+    pub info: LocationInfo,
+    pub distance_m: Option<f64>,
+}
+
+pub struct LocationResult {
+    // The latitude, longitude, and accuracy in meters.
+    pub location: Option<(f64, f64, f64)>,
+    pub source: Option<LocationSource>,
+}
+
+impl LocationResult {
+    // The response body: [lat, lon, accuracy, source], where source is the
+    // LocationSource's name, or null if the location is unknown.
+    pub fn body(&self) -> Option<(f64, f64, f64, &'static str)> {
+        let (lat, lon, accuracy) = self.location?;
+        Some((lat, lon, accuracy, self.source?.name()))
+    }
+
+    // Caveats about a location which didn't come from the client's IP.
+    pub fn warnings(&self) -> Vec<String> {
+        match self.source {
+            Some(LocationSource::Default) => {
+                vec!["IP lookup failed; using the default location".into()]
+            }
+            Some(LocationSource::AcceptLanguage) => {
+                vec!["IP lookup failed; guessed the country from Accept-Language".into()]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LocationSource {
+    Ip,
+    Default,
+    AcceptLanguage,
+}
+
+impl LocationSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocationSource::Ip => "ip",
+            LocationSource::Default => "default",
+            LocationSource::AcceptLanguage => "accept-language",
+        }
+    }
+}
+
+// Look up the client's location by IP. Unless the caller passes strict=1, fall
+// back to the configured default location, and then to a guess at the
+// client's country from its Accept-Language header.
+pub fn handle_location(
+    locator: &IpLocator,
+    request: &Request<body::Incoming>,
+    client_ip: &str,
+    context: &HandlerContext,
+) -> Result<LocationResult, HandlerError> {
+    let strict = if request.uri().query().is_some() {
+        Query::parse(request)?.get_flag("strict")?
+    } else {
+        false
+    };
+    // IP-derived positions are coarse, and reporting them too precisely can
+    // make Google distrust the emulated location.
+    let with_accuracy = |(lat, lon): (f64, f64)| (lat, lon, context.location_accuracy_floor);
+    let location = locator
+        .lookup_for_request(request, client_ip)
+        .map(|x| coarsen_location(x, context.location_precision));
+    if let Some(location) = location {
+        return Ok(LocationResult {
+            location: Some(with_accuracy(location)),
+            source: Some(LocationSource::Ip),
+        });
+    }
+    if !strict {
+        if let Some(default) = context.default_location {
+            return Ok(LocationResult {
+                location: Some(with_accuracy(default)),
+                source: Some(LocationSource::Default),
+            });
+        }
+        let guess = request
+            .headers()
+            .get("accept-language")
+            .and_then(|x| x.to_str().ok())
+            .and_then(locate_accept_language);
+        if let Some((lat, lon)) = guess {
+            let accuracy = context
+                .location_accuracy_floor
+                .max(LANGUAGE_LOCATION_ACCURACY_M);
+            return Ok(LocationResult {
+                location: Some((lat, lon, accuracy)),
+                source: Some(LocationSource::AcceptLanguage),
+            });
+        }
+    }
+    Ok(LocationResult {
+        location: None,
+        source: None,
+    })
+}
+
+// Snap a location to a grid with the given spacing in degrees, so that we
+// don't reveal more about where a client is than is needed to seed the map.
+fn coarsen_location((lat, lon): (f64, f64), precision: Option<f64>) -> (f64, f64) {
+    let Some(precision) = precision else {
+        return (lat, lon);
+    };
+    // Rounding again removes the noise from multiplying, e.g. turning
+    // 37.800000000000004 into 37.8.
+    let snap = |x: f64| ((x / precision).round() * precision * 1e9).round() / 1e9;
+    (snap(lat).clamp(-90.0, 90.0), snap(lon).clamp(-180.0, 180.0))
+}
+
+// The most IPs which may be looked up by a single /api/locations request.
+const MAX_BATCH_LOCATIONS: usize = 1000;
+
+// Look up the location of each IP in a POSTed JSON array of IP strings.
+pub async fn handle_locations(
+    locator: &IpLocator,
     request: Request<body::Incoming>,
-) -> Result<Vec<LocationInfo>, HandlerError> {
+) -> Result<Vec<Option<(f64, f64)>>, HandlerError> {
+    if request.method() != Method::POST {
+        return Err(HandlerError::MethodNotAllowed(request.method().clone()));
+    }
+    // Each IP takes at most 18 bytes as a quoted JSON string with a comma,
+    // so leave some room for whitespace on top of that.
+    let body = Limited::new(request.into_body(), MAX_BATCH_LOCATIONS * 64)
+        .collect()
+        .await
+        .map_err(|e| HandlerError::query_error(format!("failed to read request body: {}", e)))?
+        .to_bytes();
+    let ips: Vec<String> = serde_json::from_slice(&body).map_err(|e| {
+        HandlerError::query_error(format!("expected a JSON array of IP strings: {}", e))
+    })?;
+    if ips.len() > MAX_BATCH_LOCATIONS {
+        return Err(HandlerError::query_error(format!(
+            "at most {} IPs may be looked up at once",
+            MAX_BATCH_LOCATIONS
+        )));
+    }
+    Ok(ips.iter().map(|ip| locator.lookup(ip)).collect())
+}
+
+// Returns the search results, along with an ID which can be used to refer to
+// them in subsequent requests.
+pub async fn handle_search<S: Scraper, B>(
+    pool: ObjectPool<S>,
+    request: Request<B>,
+    context: &HandlerContext,
+) -> Result<(String, SearchResponse), HandlerError> {
     let args = Query::parse(&request)?;
 
+    let location = geo_location(&args, context)?;
+    let query = args.get::<String>("query")?;
+    let geojson = match args.get_opt::<String>("format")?.as_deref() {
+        None | Some("json") => false,
+        Some("geojson") => true,
+        Some(x) => {
+            return Err(HandlerError::field_error(
+                "format",
+                format!("unknown format: {}", x),
+            ))
+        }
+    };
     let mut client = pool.get().await?;
-    let location = GeoLocation {
-        latitude: args.get("latitude")?,
-        longitude: args.get("longitude")?,
-        accuracy: args.get("accuracy")?,
+    let _in_flight = context.in_flight.start();
+    let result = client.search(&query, &location).await;
+    release_client(client, &result, &context.release);
+    let results = match result? {
+        SearchResult::NotFound => vec![],
+        SearchResult::Singular(x) => vec![x],
+        SearchResult::Multiple(x) => x,
     };
-    Ok(
-        match client
-            .search(&args.get::<String>("query")?, &location)
-            .await?
-        {
-            SearchResult::NotFound => vec![],
-            SearchResult::Singular(x) => vec![x],
-            SearchResult::Multiple(x) => x,
-        },
-    )
+    let ranked = rank_by_distance(results, &location);
+    let search_id = context
+        .search_cache
+        .insert(ranked.iter().map(|x| x.info.url.clone()).collect());
+    if geojson {
+        Ok((
+            search_id,
+            SearchResponse::GeoJson(geojson_features(&ranked)),
+        ))
+    } else {
+        Ok((search_id, SearchResponse::List(ranked)))
+    }
 }
 
-pub async fn handle_reviews(
-    pool: ObjectPool<Client>,
+// Stream search results as NDJSON, one result per line as soon as its details
+// are known, rather than waiting for the whole list. Results are in the order
+// they were found, not by distance, and the last line is either a
+// {"search_id": ...} object for referring to the results by line index, or an
+// {"error": ...} object.
+pub async fn handle_search_stream<S: Scraper>(
+    pool: ObjectPool<S>,
     request: Request<body::Incoming>,
+    context: Arc<HandlerContext>,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>, HandlerError> {
     let args = Query::parse(&request)?;
+    let location = geo_location(&args, &context)?;
+    let query = args.get::<String>("query")?;
+    if args
+        .get_opt::<String>("format")?
+        .is_some_and(|x| x != "json")
+    {
+        return Err(HandlerError::field_error(
+            "format",
+            "streamed search results are always NDJSON",
+        ));
+    }
+    let mut client = pool.get().await?;
+    let in_flight = context.in_flight.start();
 
-    let location = GeoLocation {
-        latitude: args.get("latitude")?,
-        longitude: args.get("longitude")?,
-        accuracy: args.get("accuracy")?,
-    };
+    let (tx, rx) = channel::<Frame<Bytes>>(1);
+    tokio::spawn(async move {
+        let _in_flight = in_flight;
+        let (found_tx, mut found_rx) = channel::<LocationInfo>(1);
+        let mut urls = Vec::new();
+        let result = {
+            let search = client.search_incremental(&query, &location, found_tx);
+            tokio::pin!(search);
+            loop {
+                tokio::select! {
+                    Some(info) = found_rx.recv() => {
+                        if !send_search_result(&tx, &mut urls, info, &location).await {
+                            break None;
+                        }
+                    }
+                    result = &mut search => break Some(result),
+                }
+            }
+        };
+        // Nobody is left to read the results, so the search is abandoned.
+        let Some(result) = result else {
+            release_client(client, &Ok::<_, ScrapeError>(()), &context.release);
+            return;
+        };
+        release_client(client, &result, &context.release);
+        // Results sent just before the search finished may still be queued,
+        // and a single result is only returned at the end.
+        let mut rest: Vec<LocationInfo> = std::iter::from_fn(|| found_rx.try_recv().ok()).collect();
+        if let Ok(SearchResult::Singular(info)) = &result {
+            rest.push(info.clone());
+        }
+        for info in rest {
+            if !send_search_result(&tx, &mut urls, info, &location).await {
+                return;
+            }
+        }
+        let last = match result {
+            Ok(_) => json!({"search_id": context.search_cache.insert(urls)}),
+            Err(e) => json!({"error": format!("{}", e)}),
+        };
+        tx.send(Frame::data(Bytes::from(last.to_string() + "\n")))
+            .await
+            .ok();
+    });
+
+    Ok(Response::builder()
+        .header("content-type", "application/x-ndjson")
+        .body(BoxBody::new(StreamBody::new(
+            ReceiverStream::from(rx).map(Ok::<_, Infallible>),
+        )))?)
+}
+
+// Send one streamed search result, returning false if the client has gone
+// away.
+async fn send_search_result(
+    tx: &Sender<Frame<Bytes>>,
+    urls: &mut Vec<String>,
+    info: LocationInfo,
+    location: &GeoLocation,
+) -> bool {
+    let ranked = rank_by_distance(vec![info], location).remove(0);
+    urls.push(ranked.info.url.clone());
+    let line = serde_json::to_string(&ranked).unwrap() + "\n";
+    tx.send(Frame::data(Bytes::from(line))).await.is_ok()
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SearchResponse {
+    List(Vec<RankedLocation>),
+    GeoJson(serde_json::Value),
+}
+
+impl SearchResponse {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SearchResponse::List(_) => "application/json",
+            SearchResponse::GeoJson(_) => "application/geo+json",
+        }
+    }
+}
+
+// Encode search results as a GeoJSON FeatureCollection of points. Results
+// whose coordinates aren't in their URL get a null geometry.
+fn geojson_features(results: &[RankedLocation]) -> serde_json::Value {
+    let features: Vec<serde_json::Value> = results
+        .iter()
+        .map(|x| {
+            json!({
+                "type": "Feature",
+                // GeoJSON positions are ordered longitude first.
+                "geometry": x.info.coordinates().map(|(lat, lon)| json!({
+                    "type": "Point",
+                    "coordinates": [lon, lat],
+                })),
+                "properties": {
+                    "name": x.info.name,
+                    "url": x.info.url,
+                    "extra": x.info.extra,
+                    "distance_m": x.distance_m,
+                },
+            })
+        })
+        .collect();
+    json!({"type": "FeatureCollection", "features": features})
+}
+
+pub async fn handle_photos<S: Scraper, B>(
+    pool: ObjectPool<S>,
+    request: Request<B>,
+    context: &HandlerContext,
+) -> Result<Vec<String>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let url = args.get::<String>("url")?;
+    context.place_url_policy.check(&url)?;
+    let count = args.get_opt::<usize>("count")?.unwrap_or(20);
+    if count == 0 || count > 100 {
+        return Err(HandlerError::field_error(
+            "count",
+            "count must be between 1 and 100",
+        ));
+    }
+    let mut client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let result = client.list_photos(&url, count).await;
+    release_client(client, &result, &context.release);
+    Ok(result?)
+}
+
+pub async fn handle_rating<S: Scraper, B>(
+    pool: ObjectPool<S>,
+    request: Request<B>,
+    context: &HandlerContext,
+) -> Result<PlaceRating, HandlerError> {
+    let args = Query::parse(&request)?;
     let url = args.get::<String>("url")?;
+    context.place_url_policy.check(&url)?;
+    let mut client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let result = client.place_rating(&url).await;
+    release_client(client, &result, &context.release);
+    Ok(result?)
+}
+
+#[derive(Serialize)]
+pub struct RecycleResult {
+    pub recycled: usize,
+}
+
+pub async fn handle_admin_recycle<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<RecycleResult, HandlerError> {
+    if request.method() != Method::POST {
+        return Err(HandlerError::MethodNotAllowed(request.method().clone()));
+    }
+    check_admin_token(&request, context)?;
+    Ok(RecycleResult {
+        recycled: recycle_clients(&pool).await?,
+    })
+}
+
+fn check_admin_token(
+    request: &Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<(), HandlerError> {
+    let expected = context
+        .admin_token
+        .as_ref()
+        .ok_or_else(|| HandlerError::Unauthorized("admin endpoints are disabled".to_owned()))?;
+    let provided = request
+        .headers()
+        .get("authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .ok_or_else(|| HandlerError::Unauthorized("missing bearer token".to_owned()))?;
+    // Compare in constant time to avoid leaking the token through timing.
+    let matches = provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+    if matches {
+        Ok(())
+    } else {
+        Err(HandlerError::Unauthorized("invalid admin token".to_owned()))
+    }
+}
+
+#[derive(Serialize)]
+pub struct CancelResult {
+    pub cancelled: bool,
+}
+
+// Stop a running scrape by the ID it was started with, returning its client to
+// the pool. Scrape IDs are unguessable, so knowing one is enough to cancel it.
+pub fn handle_cancel(
+    request: &Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<CancelResult, HandlerError> {
+    if request.method() != Method::POST {
+        return Err(HandlerError::MethodNotAllowed(request.method().clone()));
+    }
+    let args = Query::parse(request)?;
+    let id = args.get::<String>("id")?;
+    if context.scrapes.cancel(&id) {
+        Ok(CancelResult { cancelled: true })
+    } else {
+        Err(HandlerError::field_error(
+            "id",
+            "unknown scrape ID, or the scrape already finished",
+        ))
+    }
+}
+
+// Sort locations nearest-first, keeping locations of unknown distance at the
+// end in their original order.
+fn rank_by_distance(results: Vec<LocationInfo>, location: &GeoLocation) -> Vec<RankedLocation> {
+    let mut ranked: Vec<RankedLocation> = results
+        .into_iter()
+        .map(|info| {
+            let distance_m = info
+                .coordinates()
+                .map(|(lat, lon)| location.distance_to(lat, lon));
+            RankedLocation { info, distance_m }
+        })
+        .collect();
+    ranked.sort_by(|a, b| match (a.distance_m, b.distance_m) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    ranked
+}
+
+pub async fn handle_reviews<S: Scraper, B>(
+    pool: ObjectPool<S>,
+    request: Request<B>,
+    context: &HandlerContext,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let review_request = ReviewRequest::from_query(&args, context).await?;
+    let encoding = review_encoding(&args)?;
+    let format = args
+        .get_opt::<ReviewFormat>("format")?
+        .unwrap_or(ReviewFormat::NdJson);
+    // Thin the results down to this many reviews spread across the place's
+    // history. Every review still has to be scraped to pick the sample, so
+    // this only bounds the output, unless since or max_reviews stop the scrape
+    // early.
+    let sample = args.get_opt::<usize>("sample")?;
+    // NDJSON and JSON arrays are only limited if the caller asks for it, and
+    // so is a sampled JSON object, since only the sample is buffered.
+    let max_reviews = match format {
+        ReviewFormat::NdJson | ReviewFormat::JsonArray => args.get_opt::<usize>("max_reviews")?,
+        ReviewFormat::Json if sample.is_some() => args.get_opt::<usize>("max_reviews")?,
+        ReviewFormat::Json => Some(max_buffered_reviews(&args)?),
+    };
+    // Start the stream by reporting how many reviews the place has, so that
+    // clients can show progress or give up early.
+    let report_total = args.get_flag("report_total")?;
+    // Report how far the scrape has gotten after every this many pages.
+    let progress_every = args.get_opt::<usize>("progress_every")?;
+    // Emitting reviews oldest-first holds them all until the scrape ends, so
+    // it fails once more than MAX_BUFFERED_REVIEWS are collected.
+    let order = args
+        .get_opt::<ReviewOrder>("order")?
+        .unwrap_or(ReviewOrder::Newest);
+    if sample == Some(0) {
+        return Err(HandlerError::field_error(
+            "sample",
+            "sample must be positive",
+        ));
+    }
+    if let (ReviewFormat::Json, Some(sample)) = (&format, sample) {
+        if sample > MAX_BUFFERED_REVIEWS {
+            return Err(HandlerError::field_error(
+                "sample",
+                format!("sample may be at most {}", MAX_BUFFERED_REVIEWS),
+            ));
+        }
+    }
+    if progress_every == Some(0) {
+        return Err(HandlerError::field_error(
+            "progress_every",
+            "progress_every must be positive",
+        ));
+    }
+    let mut encoder = format.encoder(encoding, &review_request.url);
+    let content_type = encoder.content_type();
+    if let Some(max_age) = args.get_opt::<f64>("max_age")? {
+        let max_age = Duration::try_from_secs_f64(max_age)
+            .map_err(|e| HandlerError::field_error("max_age", e))?;
+        // The cache is refreshed by scraping newest-first, so it can't serve
+        // Google's other orders.
+        if review_request.options.google_sort.is_some() {
+            return Err(HandlerError::field_error(
+                "google_sort",
+                "google_sort cannot be combined with max_age",
+            ));
+        }
+        return handle_cached_reviews(
+            pool,
+            review_request,
+            max_age,
+            ReviewSelection {
+                max_reviews,
+                sample,
+                order,
+            },
+            encoder,
+            context,
+        )
+        .await;
+    }
     let mut client = pool.get().await?;
+    let in_flight = context.in_flight.start();
+    let release = context.release.clone();
+    let completeness = context.completeness.clone();
 
-    let (tx, rx) = channel::<Bytes>(1);
+    let (tx, mut rx) = channel::<Frame<Bytes>>(1);
+    let (scrape, abort) = context.scrapes.register();
+    let scrape_id = scrape.id.clone();
+    // A JSON object is only useful once it's complete, so if a scrape fails
+    // before any of it is written, respond with the error like any other
+    // handler would instead.
+    let (early_error_tx, mut early_error_rx) = oneshot::channel::<HandlerError>();
+    let early_error_tx = matches!(format, ReviewFormat::Json).then_some(early_error_tx);
+    let hold_response = early_error_tx.is_some();
 
     tokio::spawn(async move {
-        let results = client.list_reviews(&url, &location).await;
-        drop(client);
-        match results {
-            Err(e) => {
-                tx.send(Bytes::from(
-                    serde_json::to_string(&json!({"error": format!("{}", e)})).unwrap() + "\n",
-                ))
+        let _in_flight = in_flight;
+        let _scrape = scrape;
+        let scrape = async {
+            let started = Instant::now();
+            let results = client
+                .list_reviews(
+                    &review_request.url,
+                    &review_request.location,
+                    &review_request.options,
+                )
                 .await
-                .ok();
-            }
-            Ok(mut it) => loop {
-                match it.next().await {
-                    Err(e) => {
-                        tx.send(Bytes::from(
-                            serde_json::to_string(&json!({"error": format!("{}", e)})).unwrap()
-                                + "\n",
-                        ))
+                .map(|mut it| {
+                    review_request.configure(&mut it, started);
+                    it
+                });
+            let mut outcome = release_review_client(client, &results, &release);
+            // Whether any of the output has been written.
+            let mut wrote_output = false;
+            if let (true, Ok(it)) = (report_total, &results) {
+                if let Some(total) = it.total_count() {
+                    if tx
+                        .send(Frame::data(encoder.encode_total(total)))
                         .await
-                        .ok();
+                        .is_err()
+                    {
                         return;
                     }
-                    Ok(Some(x)) => {
-                        if !tx
-                            .send(Bytes::from(serde_json::to_string(&x).unwrap() + "\n"))
+                    wrote_output = true;
+                }
+            }
+            let mut sampler = sample.map(EvenSampler::new);
+            let mut oldest_first = (order == ReviewOrder::Oldest).then(Vec::new);
+            let mut count = 0;
+            // Reviews scraped before any filtering, for the completeness check.
+            let mut scraped = 0;
+            let mut skipped = 0;
+            let mut pages = 0;
+            let total = results.as_ref().ok().and_then(|it| it.total_count());
+            // Either whether the results were truncated, or the error which ended
+            // the scrape.
+            let outcome = match results {
+                Err(e) => Err(HandlerError::from(e)),
+                Ok(mut it) => loop {
+                    match outcome.check(it.next().await) {
+                        Err(e) => break Err(HandlerError::from(e)),
+                        Ok(Some(x)) => {
+                            scraped += x.len();
+                            skipped = it.skipped_reviews();
+                            let mut x: Vec<Review> = x
+                                .into_iter()
+                                .filter(|r| review_request.filter.matches(r))
+                                .collect();
+                            let mut truncated = false;
+                            if let Some(max) = max_reviews {
+                                if count + x.len() > max {
+                                    x.truncate(max - count);
+                                    truncated = true;
+                                }
+                            }
+                            count += x.len();
+                            if let Some(sampler) = &mut sampler {
+                                x.into_iter().for_each(|r| sampler.push(r));
+                            } else if let Some(buffer) = &mut oldest_first {
+                                if buffer.len() + x.len() > MAX_BUFFERED_REVIEWS {
+                                    oldest_first = None;
+                                    count = 0;
+                                    break Err(HandlerError::field_error(
+                                        "order",
+                                        format!(
+                                            "too many reviews to emit oldest-first (at most {})",
+                                            MAX_BUFFERED_REVIEWS
+                                        ),
+                                    ));
+                                }
+                                buffer.extend(x);
+                            } else {
+                                if tx.send(Frame::data(encoder.encode_page(&x))).await.is_err() {
+                                    return;
+                                }
+                                wrote_output = true;
+                            }
+                            pages += 1;
+                            if progress_every.is_some_and(|n| pages % n == 0) {
+                                let frame = encoder.encode_progress(scraped, pages);
+                                if !frame.is_empty() && tx.send(Frame::data(frame)).await.is_err() {
+                                    return;
+                                }
+                            }
+                            if truncated {
+                                break Ok(true);
+                            }
+                        }
+                        Ok(None) => break Ok(it.hit_deadline()),
+                    }
+                },
+            };
+            let buffered = match sampler {
+                Some(sampler) => Some(sampler.finish()),
+                None => oldest_first,
+            };
+            if let Some(mut x) = buffered {
+                if order == ReviewOrder::Oldest {
+                    x.reverse();
+                }
+                count = x.len();
+                if tx.send(Frame::data(encoder.encode_page(&x))).await.is_err() {
+                    return;
+                }
+                wrote_output = true;
+            }
+            let mut warnings: Vec<String> = skipped_reviews_warning(skipped).into_iter().collect();
+            if matches!(outcome, Ok(false)) && review_request.expects_all_reviews() {
+                warnings.extend(completeness.check(total, scraped));
+            }
+            for warning in warnings {
+                let frame = encoder.encode_warning(&warning);
+                if !frame.is_empty() && tx.send(Frame::data(frame)).await.is_err() {
+                    return;
+                }
+            }
+            let last = match (outcome, early_error_tx) {
+                (Ok(truncated), _) => encoder.encode_end(truncated),
+                (Err(e), Some(early_error_tx)) if !wrote_output => {
+                    early_error_tx.send(e).ok();
+                    return;
+                }
+                (Err(HandlerError::ScrapeError(e)), _) => encoder.encode_error(&format!("{}", e)),
+                (Err(HandlerError::QueryError { message, .. }), _) => {
+                    encoder.encode_error(&message)
+                }
+                (Err(e), _) => encoder.encode_error(&format!("{}", e)),
+            };
+            if !last.is_empty() && tx.send(Frame::data(last)).await.is_err() {
+                return;
+            }
+            // Report the total in a trailer, for clients which would rather not
+            // count the reviews in the body themselves.
+            let mut trailers = HeaderMap::new();
+            trailers.insert(REVIEW_COUNT_TRAILER, HeaderValue::from(count));
+            tx.send(Frame::trailers(trailers)).await.ok();
+        };
+        // A cancelled scrape drops its client and stops mid-stream, so end the
+        // output with an error.
+        if Abortable::new(scrape, abort).await.is_err() {
+            let last = encoder.encode_error("scrape was cancelled");
+            if !last.is_empty() {
+                tx.send(Frame::data(last)).await.ok();
+            }
+        }
+    });
+
+    // Hold the response until the first of the output is written or the
+    // scrape fails. The error is checked first, since the output ends right
+    // after it's sent.
+    let mut first = None;
+    if hold_response {
+        tokio::select! {
+            biased;
+            Ok(e) = &mut early_error_rx => return Err(e),
+            frame = rx.recv() => first = frame,
+        }
+    }
+
+    Ok(Response::builder()
+        .header("content-type", content_type)
+        .header("x-scrape-id", scrape_id)
+        .header("trailer", REVIEW_COUNT_TRAILER)
+        .body(BoxBody::new(StreamBody::new(
+            stream::iter(first)
+                .chain(ReceiverStream::from(rx))
+                .map(Ok::<_, Infallible>),
+        )))?)
+}
+
+// The most search results whose reviews can be merged by one request.
+const MAX_MERGED_PLACES: usize = 10;
+
+// Search for a query and stream the reviews of every place it turns up (up to
+// max_places, nearest first), merged together as they are scraped. Each review
+// is tagged with the URL of its place. Only --merge-concurrency places are
+// scraped at once, so that a single request can't take over the whole pool.
+//
+// A place which fails doesn't stop the others. Its error is reported as a
+// warning at the end of the stream, which only ends with an error if every
+// place failed.
+pub async fn handle_merged_reviews<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let query = args.get::<String>("query")?;
+    let template = ReviewRequest::for_url(&args, String::new(), context)?;
+    let encoding = review_encoding(&args)?;
+    let format = args
+        .get_opt::<ReviewFormat>("format")?
+        .unwrap_or(ReviewFormat::NdJson);
+    let max_reviews = max_buffered_reviews(&args)?;
+    let max_places = args.get_opt::<usize>("max_places")?.unwrap_or(5);
+    if max_places == 0 || max_places > MAX_MERGED_PLACES {
+        return Err(HandlerError::field_error(
+            "max_places",
+            format!("max_places must be between 1 and {}", MAX_MERGED_PLACES),
+        ));
+    }
+
+    let mut client = pool.get().await?;
+    let in_flight = context.in_flight.start();
+    let result = client.search(&query, &template.location).await;
+    release_client(client, &result, &context.release);
+    let places = match result? {
+        SearchResult::NotFound => vec![],
+        SearchResult::Singular(x) => vec![x],
+        SearchResult::Multiple(x) => x,
+    };
+    let requests: Vec<ReviewRequest> = rank_by_distance(places, &template.location)
+        .into_iter()
+        .filter(|x| context.place_url_policy.check(&x.info.url).is_ok())
+        .take(max_places)
+        .map(|x| ReviewRequest {
+            url: x.info.url,
+            ..template.clone()
+        })
+        .collect();
+
+    let requests_len = requests.len();
+    let mut encoder = format.encoder(encoding, &query);
+    let content_type = encoder.content_type();
+    let release = context.release.clone();
+    let concurrency = context.merge_concurrency;
+    let (tx, rx) = channel::<Frame<Bytes>>(1);
+    let (scrape, abort) = context.scrapes.register();
+    let scrape_id = scrape.id.clone();
+
+    tokio::spawn(async move {
+        let _in_flight = in_flight;
+        let _scrape = scrape;
+        let (page_tx, page_rx) = channel::<Result<Vec<Review>, String>>(1);
+        // Once every place is done, page_tx is dropped, ending page_rx.
+        let producer = async move {
+            stream::iter(requests)
+                .map(|request| {
+                    send_place_reviews(pool.clone(), request, release.clone(), page_tx.clone())
+                })
+                .buffer_unordered(concurrency)
+                .collect::<Vec<()>>()
+                .await;
+        };
+        let consumer = async {
+            let mut page_rx = page_rx;
+            let mut count = 0;
+            let mut errors = Vec::new();
+            let outcome = loop {
+                match page_rx.recv().await {
+                    None if requests_len > 0 && errors.len() == requests_len => {
+                        break Err(errors.remove(0));
+                    }
+                    None => break Ok(false),
+                    Some(Err(e)) => errors.push(e),
+                    Some(Ok(mut page)) => {
+                        let truncated = count + page.len() > max_reviews;
+                        page.truncate(max_reviews - count);
+                        count += page.len();
+                        if tx
+                            .send(Frame::data(encoder.encode_page(&page)))
                             .await
-                            .is_ok()
+                            .is_err()
                         {
                             return;
                         }
+                        if truncated {
+                            break Ok(true);
+                        }
                     }
-                    Ok(None) => return,
                 }
-            },
+            };
+            if outcome.is_ok() {
+                for e in errors {
+                    let frame = encoder.encode_warning(&e);
+                    if !frame.is_empty() && tx.send(Frame::data(frame)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            let last = match outcome {
+                Ok(truncated) => encoder.encode_end(truncated),
+                Err(e) => encoder.encode_error(&e),
+            };
+            if !last.is_empty() && tx.send(Frame::data(last)).await.is_err() {
+                return;
+            }
+            let mut trailers = HeaderMap::new();
+            trailers.insert(REVIEW_COUNT_TRAILER, HeaderValue::from(count));
+            tx.send(Frame::trailers(trailers)).await.ok();
+        };
+        // Once the output ends (or the client goes away), drop the producer
+        // so that its scrapes stop and their clients go back to the pool.
+        // Until then, the consumer keeps draining what the producer sends.
+        let scrape = async {
+            tokio::pin!(consumer);
+            tokio::select! {
+                _ = &mut consumer => {}
+                _ = producer => consumer.await,
+            }
+        };
+        if Abortable::new(scrape, abort).await.is_err() {
+            let last = encoder.encode_error("scrape was cancelled");
+            if !last.is_empty() {
+                tx.send(Frame::data(last)).await.ok();
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .header("content-type", content_type)
+        .header("x-scrape-id", scrape_id)
+        .header("trailer", REVIEW_COUNT_TRAILER)
+        .body(BoxBody::new(StreamBody::new(
+            ReceiverStream::from(rx).map(Ok::<_, Infallible>),
+        )))?)
+}
+
+// Scrape one place for handle_merged_reviews, sending its reviews on page by
+// page, or else the error which ended the scrape.
+async fn send_place_reviews<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: ReviewRequest,
+    release: ClientRelease,
+    tx: Sender<Result<Vec<Review>, String>>,
+) {
+    if let Err(e) = send_place_review_pages(pool, &request, &release, &tx).await {
+        tx.send(Err(format!("{}: {}", request.url, e))).await.ok();
+    }
+}
+
+async fn send_place_review_pages<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: &ReviewRequest,
+    release: &ClientRelease,
+    tx: &Sender<Result<Vec<Review>, String>>,
+) -> Result<(), HandlerError> {
+    // Don't tie up a client if the output has already ended.
+    if tx.is_closed() {
+        return Ok(());
+    }
+    let mut client = pool.get().await?;
+    let started = Instant::now();
+    let results = client
+        .list_reviews(&request.url, &request.location, &request.options)
+        .await;
+    let mut outcome = release_review_client(client, &results, release);
+    let mut it = results?;
+    request.configure(&mut it, started);
+    while let Some(page) = outcome.check(it.next().await)? {
+        let page: Vec<Review> = page
+            .into_iter()
+            .filter(|r| request.filter.matches(r))
+            .map(|r| Review {
+                place: Some(request.url.clone()),
+                ..r
+            })
+            .collect();
+        if tx.send(Ok(page)).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Which of the scraped reviews to respond with, and in what order.
+struct ReviewSelection {
+    max_reviews: Option<usize>,
+    sample: Option<usize>,
+    order: ReviewOrder,
+}
+
+// Serve reviews from the review cache if they are at most max_age old, and
+// otherwise refresh the cache first by scraping only the reviews newer than the
+// cached ones.
+async fn handle_cached_reviews<S: Scraper>(
+    pool: ObjectPool<S>,
+    mut review_request: ReviewRequest,
+    max_age: Duration,
+    selection: ReviewSelection,
+    mut encoder: Box<dyn OutputEncoder>,
+    context: &HandlerContext,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, HandlerError> {
+    let key = review_request.cache_key();
+    let cache = &context.review_cache;
+    let (status, age, cached) = match cache.get(&key) {
+        Some((age, cached)) if age <= max_age => (CacheStatus::Hit, age, cached),
+        _ => {
+            let lock = cache.refresh_lock(&key);
+            let _guard = lock.lock().await;
+            refresh_cached_reviews(pool, &mut review_request, max_age, context).await?
+        }
+    };
+    cache.record(status);
+
+    let mut reviews: Vec<&Review> = cached
+        .reviews
+        .iter()
+        .filter(|r| review_request.filter.matches(r))
+        .collect();
+    let mut truncated = cached.truncated;
+    if let Some(max) = selection.max_reviews {
+        if reviews.len() > max {
+            reviews.truncate(max);
+            truncated = true;
+        }
+    }
+    let mut reviews: Vec<Review> = match selection.sample {
+        Some(size) => {
+            let mut sampler = EvenSampler::new(size);
+            reviews.into_iter().for_each(|r| sampler.push(r.clone()));
+            sampler.finish()
+        }
+        None => reviews.into_iter().cloned().collect(),
+    };
+    if selection.order == ReviewOrder::Oldest {
+        reviews.reverse();
+    }
+    let mut trailers = HeaderMap::new();
+    trailers.insert(REVIEW_COUNT_TRAILER, HeaderValue::from(reviews.len()));
+    let frames = vec![
+        Frame::data(encoder.encode_page(&reviews)),
+        Frame::data(encoder.encode_end(truncated)),
+        Frame::trailers(trailers),
+    ];
+    Ok(Response::builder()
+        .header("content-type", encoder.content_type())
+        .header("trailer", REVIEW_COUNT_TRAILER)
+        .header("age", age.as_secs())
+        .header("x-cache", status.to_string())
+        .header("cache-control", format!("max-age={}", max_age.as_secs()))
+        .body(BoxBody::new(StreamBody::new(futures::stream::iter(
+            frames.into_iter().map(Ok::<_, Infallible>),
+        ))))?)
+}
+
+// Scrape any reviews which are newer than the cached ones for the request's
+// place, unless another request already did so while we waited for the
+// refresh lock.
+async fn refresh_cached_reviews<S: Scraper>(
+    pool: ObjectPool<S>,
+    review_request: &mut ReviewRequest,
+    max_age: Duration,
+    context: &HandlerContext,
+) -> Result<(CacheStatus, Duration, Arc<CachedReviews>), HandlerError> {
+    let key = review_request.cache_key();
+    let previous = match context.review_cache.get(&key) {
+        Some((age, cached)) if age <= max_age => {
+            return Ok((CacheStatus::Coalesced, age, cached));
         }
+        previous => previous.map(|(_, x)| x),
+    };
+    // The cache holds every review, so the caller's filter is only applied
+    // once the cached reviews are served.
+    let filter = std::mem::replace(&mut review_request.filter, ReviewFilter::all());
+    review_request.options.since = previous.as_ref().and_then(|x| x.newest_timestamp());
+    // Raw review data is only useful for debugging a live scrape, so keep it
+    // out of the cache.
+    review_request.options.raw_samples = 0;
+    let client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let result = collect_reviews(
+        client,
+        review_request,
+        MAX_BUFFERED_REVIEWS,
+        &context.release,
+    )
+    .await;
+    review_request.filter = filter;
+    let (reviews, truncated, _) = result?;
+    let cached = Arc::new(match previous {
+        Some(previous) if !truncated => merge_reviews(reviews, &previous),
+        _ => CachedReviews { reviews, truncated },
     });
+    context.review_cache.insert(key, cached.clone());
+    Ok((CacheStatus::Miss, Duration::ZERO, cached))
+}
+
+// What makes a review the same as one scraped earlier. Some reviews have no
+// ID (it's left empty), so those are compared by their contents instead.
+#[derive(PartialEq, Eq, Hash)]
+enum ReviewKey {
+    Id(String),
+    Content(u64, String, String),
+}
 
-    Ok(Response::new(BoxBody::new(StreamBody::new(
-        ReceiverStream::from(rx).map(|x| -> Result<_, Infallible> { Ok(Frame::data(x)) }),
-    ))))
+impl ReviewKey {
+    fn new(review: &Review) -> Self {
+        if review.review_id.is_empty() {
+            ReviewKey::Content(
+                review.timestamp.to_bits(),
+                review.author.clone(),
+                review.content.clone(),
+            )
+        } else {
+            ReviewKey::Id(review.review_id.clone())
+        }
+    }
+}
+
+// Combine newly scraped reviews with a previous (complete) result, keeping the
+// newest-first order and dropping reviews which were scraped twice.
+fn merge_reviews(new_reviews: Vec<Review>, previous: &CachedReviews) -> CachedReviews {
+    let new_keys: HashSet<ReviewKey> = new_reviews.iter().map(ReviewKey::new).collect();
+    let mut reviews = new_reviews;
+    reviews.extend(
+        previous
+            .reviews
+            .iter()
+            .filter(|x| !new_keys.contains(&ReviewKey::new(x)))
+            .cloned(),
+    );
+    let mut truncated = previous.truncated;
+    if reviews.len() > MAX_BUFFERED_REVIEWS {
+        reviews.truncate(MAX_BUFFERED_REVIEWS);
+        truncated = true;
+    }
+    CachedReviews { reviews, truncated }
+}
+
+type ReviewSocket = WebSocketStream<TokioIo<Upgraded>>;
+
+// Serve the review stream over a WebSocket. After the upgrade, the client
+// sends the usual /api/reviews query string as its first text message, and
+// may later send "stop" to end the scrape early.
+//
+// Each server message is a JSON object whose "type" is one of:
+//  - "started": the "scrape_id" which /api/cancel accepts, before anything
+//    else.
+//  - "total": the place's total review count, if known, before any pages.
+//  - "page": a page of "reviews", with the running "count" so far and a
+//    "resume_token" which continues the scrape after this page when passed
+//    back in the query (or null after the last page).
+//  - "done" or "error": the last message, after which the socket is closed.
+//    A "done" message may have a "warnings" list, e.g. if the scrape looks
+//    incomplete.
+pub fn handle_reviews_ws<S: Scraper>(
+    pool: ObjectPool<S>,
+    mut request: Request<body::Incoming>,
+    context: Arc<HandlerContext>,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, HandlerError> {
+    let headers = request.headers();
+    let is_upgrade = headers
+        .get("upgrade")
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.eq_ignore_ascii_case("websocket"));
+    let key = headers
+        .get("sec-websocket-key")
+        .filter(|_| is_upgrade)
+        .ok_or_else(|| HandlerError::query_error("expected a WebSocket upgrade request"))?;
+    let accept = derive_accept_key(key.as_bytes());
+    let upgrade = hyper::upgrade::on(&mut request);
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => {
+                let socket =
+                    WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None)
+                        .await;
+                serve_review_socket(pool, socket, &context).await;
+            }
+            Err(e) => println!("WebSocket upgrade failed: {}", e),
+        }
+    });
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header("connection", "upgrade")
+        .header("upgrade", "websocket")
+        .header("sec-websocket-accept", accept)
+        .body(BoxBody::new(Full::new(Bytes::new())))?)
+}
+
+async fn serve_review_socket<S: Scraper>(
+    pool: ObjectPool<S>,
+    socket: ReviewSocket,
+    context: &HandlerContext,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let query = loop {
+        match stream.next().await {
+            Some(Ok(Message::Text(x))) => break x,
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+            Some(Ok(_)) => continue,
+        }
+    };
+    // While scraping, watch for the client asking us to stop (or going away).
+    let stop = async move {
+        while let Some(Ok(message)) = stream.next().await {
+            match message {
+                Message::Text(x) if x.trim() == "stop" => return,
+                Message::Close(_) => return,
+                _ => {}
+            }
+        }
+    };
+    let (scrape, abort) = context.scrapes.register();
+    let scrape_future = send_reviews_to_socket(pool, &query, &mut sink, stop, &scrape.id, context);
+    let last = match Abortable::new(scrape_future, abort).await {
+        Ok(Ok(x)) => x,
+        Ok(Err(e)) => json!({"type": "error", "error": format!("{}", e)}),
+        Err(_) => json!({"type": "error", "error": "scrape was cancelled"}),
+    };
+    sink.send(Message::Text(last.to_string())).await.ok();
+    sink.close().await.ok();
+}
+
+// Scrape and send pages of reviews until the scrape ends or stop completes,
+// returning the final message to send.
+async fn send_reviews_to_socket<S: Scraper, F: Future<Output = ()>>(
+    pool: ObjectPool<S>,
+    query: &str,
+    sink: &mut SplitSink<ReviewSocket, Message>,
+    stop: F,
+    scrape_id: &str,
+    context: &HandlerContext,
+) -> Result<serde_json::Value, HandlerError> {
+    let args = Query::from_query_string(query.trim_start_matches('?'));
+    let review_request = ReviewRequest::from_query(&args, context).await?;
+    let encoding = review_encoding(&args)?;
+    let max_reviews = args.get_opt::<usize>("max_reviews")?;
+    let resume_token = args.get_opt::<String>("resume_token")?;
+    let message = json!({"type": "started", "scrape_id": scrape_id});
+    if sink.send(Message::Text(message.to_string())).await.is_err() {
+        return Ok(json!({"type": "done", "count": 0, "truncated": true}));
+    }
+    let _in_flight = context.in_flight.start();
+    let started = Instant::now();
+    let (mut it, mut outcome) = match resume_token {
+        // Later pages are fetched without a browser, so no client is needed.
+        Some(token) => (
+            ReviewIter::from_token(&review_request.url, &token)
+                .map_err(|e| HandlerError::field_error("resume_token", e))?,
+            ScrapeOutcome::new(&context.release.errors),
+        ),
+        None => {
+            let mut client = pool.get().await?;
+            let results = client
+                .list_reviews(
+                    &review_request.url,
+                    &review_request.location,
+                    &review_request.options,
+                )
+                .await;
+            let outcome = release_review_client(client, &results, &context.release);
+            (results?, outcome)
+        }
+    };
+    review_request.configure(&mut it, started);
+
+    let done = |count: usize, truncated: bool| json!({"type": "done", "count": count, "truncated": truncated});
+    if let Some(total) = it.total_count() {
+        let message = json!({"type": "total", "total_reviews": total});
+        if sink.send(Message::Text(message.to_string())).await.is_err() {
+            return Ok(done(0, true));
+        }
+    }
+    tokio::pin!(stop);
+    let mut count = 0;
+    let mut scraped = 0;
+    loop {
+        // Dropping a half-fetched page is fine, since the scrape is over.
+        let page = tokio::select! {
+            page = it.next() => outcome.check(page)?,
+            _ = &mut stop => return Ok(done(count, true)),
+        };
+        let Some(page) = page else {
+            let mut message = done(count, it.hit_deadline());
+            let mut warnings: Vec<String> = skipped_reviews_warning(it.skipped_reviews())
+                .into_iter()
+                .collect();
+            if !it.hit_deadline() && review_request.expects_all_reviews() {
+                warnings.extend(context.completeness.check(it.total_count(), scraped));
+            }
+            if !warnings.is_empty() {
+                message["warnings"] = warnings.into();
+            }
+            return Ok(message);
+        };
+        scraped += page.len();
+        let mut page: Vec<Review> = page
+            .into_iter()
+            .filter(|r| review_request.filter.matches(r))
+            .collect();
+        let mut truncated = false;
+        if let Some(max) = max_reviews {
+            if count + page.len() > max {
+                page.truncate(max - count);
+                truncated = true;
+            }
+        }
+        count += page.len();
+        let message = format!(
+            "{{\"type\":\"page\",\"count\":{},\"resume_token\":{},\"reviews\":{}}}",
+            count,
+            serde_json::to_string(&it.next_token()).unwrap(),
+            encoding.encode(&page)
+        );
+        if sink.send(Message::Text(message)).await.is_err() || truncated {
+            return Ok(done(count, true));
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PoolStatus {
+    pub capacity: usize,
+    pub available: usize,
+    pub in_flight: usize,
+}
+
+impl PoolStatus {
+    pub fn new<T>(pool: &ObjectPool<T>, context: &HandlerContext) -> Self {
+        Self {
+            capacity: pool.capacity(),
+            available: pool.available(),
+            in_flight: context.in_flight.get(),
+        }
+    }
+
+    // Render the status in the Prometheus text exposition format.
+    pub fn prometheus_metrics(&self) -> String {
+        let mut result = String::new();
+        for (name, help, value) in [
+            (
+                "review_plot_pool_capacity",
+                "Number of browsers in the client pool.",
+                self.capacity,
+            ),
+            (
+                "review_plot_pool_available",
+                "Number of idle browsers in the client pool.",
+                self.available,
+            ),
+            (
+                "review_plot_in_flight_scrapes",
+                "Number of scrapes currently using a browser.",
+                self.in_flight,
+            ),
+        ] {
+            result.push_str(&format!(
+                "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"
+            ));
+        }
+        result
+    }
+}
+
+#[derive(Serialize)]
+pub struct VersionInfo {
+    pub version: &'static str,
+    pub git_commit: &'static str,
+    pub driver: String,
+    pub pool_size: usize,
+    pub headless: bool,
+}
+
+impl VersionInfo {
+    pub fn new(driver: &str, pool_size: usize, headless: bool) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GIT_COMMIT"),
+            driver: redact_url_credentials(driver),
+            pool_size,
+            headless,
+        }
+    }
+}
+
+fn redact_url_credentials(raw: &str) -> String {
+    match url::Url::parse(raw) {
+        Ok(mut parsed) => {
+            if parsed.password().is_some() || !parsed.username().is_empty() {
+                parsed.set_username("").ok();
+                parsed.set_password(None).ok();
+            }
+            parsed.to_string()
+        }
+        Err(_) => "<unparseable>".to_owned(),
+    }
+}
+
+const REVIEW_COUNT_TRAILER: &str = "x-review-count";
+
+// The most reviews that will be buffered in memory for a single response.
+const MAX_BUFFERED_REVIEWS: usize = 10000;
+
+pub async fn handle_review_summary<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<Envelope<ReviewSummary>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let review_request = ReviewRequest::from_query(&args, context).await?;
+    let max_reviews = max_buffered_reviews(&args)?;
+    let client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let (reviews, truncated, skipped) =
+        collect_reviews(client, &review_request, max_reviews, &context.release).await?;
+    Ok(Envelope {
+        data: ReviewSummary::new(&reviews, truncated, unix_now()),
+        warnings: skipped_reviews_warning(skipped).into_iter().collect(),
+    })
+}
+
+pub async fn handle_review_distribution<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<Envelope<RatingDistribution>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let review_request = ReviewRequest::from_query(&args, context).await?;
+    let max_reviews = max_buffered_reviews(&args)?;
+    let interval = args
+        .get_opt::<Interval>("interval")?
+        .unwrap_or(Interval::Month);
+    let client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let (reviews, truncated, skipped) =
+        collect_reviews(client, &review_request, max_reviews, &context.release).await?;
+    Ok(Envelope {
+        data: RatingDistribution::new(&reviews, truncated, interval),
+        warnings: skipped_reviews_warning(skipped).into_iter().collect(),
+    })
+}
+
+// The most reviews which may be requested for each star rating.
+const MAX_REVIEWS_PER_RATING: usize = 1000;
+
+// Returns up to per_rating reviews for each star rating from 1 to 5, keyed by
+// the rounded rating.
+pub async fn handle_reviews_by_rating<S: Scraper>(
+    pool: ObjectPool<S>,
+    request: Request<body::Incoming>,
+    context: &HandlerContext,
+) -> Result<BTreeMap<u8, Vec<Review>>, HandlerError> {
+    let args = Query::parse(&request)?;
+    let review_request = ReviewRequest::from_query(&args, context).await?;
+    let per_rating = args.get_opt::<usize>("per_rating")?.unwrap_or(20);
+    if per_rating == 0 || per_rating > MAX_REVIEWS_PER_RATING {
+        return Err(HandlerError::field_error(
+            "per_rating",
+            format!(
+                "per_rating must be between 1 and {}",
+                MAX_REVIEWS_PER_RATING
+            ),
+        ));
+    }
+    let mut client = pool.get().await?;
+    let _in_flight = context.in_flight.start();
+    let started = Instant::now();
+    let results = client
+        .list_reviews(
+            &review_request.url,
+            &review_request.location,
+            &review_request.options,
+        )
+        .await;
+    let mut outcome = release_review_client(client, &results, &context.release);
+    let mut it = results?;
+    review_request.configure(&mut it, started);
+
+    let mut buckets: BTreeMap<u8, Vec<Review>> = (1..=5).map(|x| (x, Vec::new())).collect();
+    while let Some(page) = outcome.check(it.next().await)? {
+        for review in page
+            .into_iter()
+            .filter(|r| review_request.filter.matches(r))
+        {
+            let bucket = buckets
+                .get_mut(&(review.rating.round().clamp(1.0, 5.0) as u8))
+                .unwrap();
+            if bucket.len() < per_rating {
+                bucket.push(review);
+            }
+        }
+        // There's no need to keep scraping once every bucket is full.
+        if buckets.values().all(|x| x.len() >= per_rating) {
+            break;
+        }
+    }
+    Ok(buckets)
+}
+
+// The number of reviews to include raw JSON for when raw=1 is passed.
+const RAW_REVIEW_SAMPLES: usize = 5;
+
+// The parameters shared by every endpoint which scrapes a place's reviews.
+#[derive(Clone)]
+struct ReviewRequest {
+    url: String,
+    location: GeoLocation,
+    options: ReviewListOptions,
+    pagination_delay: Duration,
+    max_duration: Option<Duration>,
+    page_cache: Option<Arc<PageCache>>,
+    filter: ReviewFilter,
+}
+
+impl ReviewRequest {
+    // Apply the request's pacing and time limit to a scrape which started at
+    // the given time.
+    fn configure(&self, it: &mut ReviewIter, started: Instant) {
+        it.set_page_delay(self.pagination_delay);
+        if let Some(max_duration) = self.max_duration {
+            it.set_deadline(started + max_duration);
+        }
+        if let Some(page_cache) = &self.page_cache {
+            it.set_page_cache(page_cache.clone());
+        }
+    }
+
+    // Whether a scrape which runs to the end should see every review the place
+    // panel counts, rather than just the newest or those in one language.
+    fn expects_all_reviews(&self) -> bool {
+        self.options.since.is_none() && self.options.language.is_none()
+    }
+
+    // Reviews are cached per place and language; everything else about the
+    // request is applied when serving from the cache.
+    fn cache_key(&self) -> String {
+        format!(
+            "{}\n{}",
+            self.options.language.as_deref().unwrap_or_default(),
+            self.url
+        )
+    }
+
+    async fn from_query(args: &Query, context: &HandlerContext) -> Result<Self, HandlerError> {
+        let url = match args.get_opt::<String>("search_id")? {
+            Some(search_id) => {
+                let index = args.get::<usize>("index")?;
+                context
+                    .search_cache
+                    .resolve(&search_id, index)
+                    .ok_or_else(|| {
+                        HandlerError::field_error(
+                            "search_id",
+                            "unknown or expired search_id, or index out of range",
+                        )
+                    })?
+            }
+            None => args.get::<String>("url")?,
+        };
+        // Share links only redirect to the place, so resolve them before
+        // deciding whether the place may be scraped.
+        let url = if is_short_link(&url) {
+            resolve_short_link(&url)
+                .await
+                .map_err(|e| HandlerError::field_error("url", e))?
+        } else {
+            url
+        };
+        context.place_url_policy.check(&url)?;
+        Self::for_url(args, url, context)
+    }
+
+    // Parse everything but the place from the query. The caller is
+    // responsible for checking the URL against the place URL policy.
+    fn for_url(args: &Query, url: String, context: &HandlerContext) -> Result<Self, HandlerError> {
+        let location = geo_location(args, context)?;
+        let pagination_delay = match args.get_opt::<f64>("pagination_delay")? {
+            Some(x) => Duration::try_from_secs_f64(x)
+                .map_err(|e| HandlerError::field_error("pagination_delay", e))?,
+            None => context.pagination_delay,
+        };
+        let options = ReviewListOptions {
+            language: args.get_opt::<String>("lang")?,
+            since: args.get_opt::<f64>("since")?,
+            max_total_reviews: if args.get_flag("allow_large")? {
+                None
+            } else {
+                context.max_total_reviews
+            },
+            raw_samples: if args.get_flag("raw")? {
+                if !context.debug {
+                    return Err(HandlerError::field_error(
+                        "raw",
+                        "raw review data requires the server to run with --debug",
+                    ));
+                }
+                RAW_REVIEW_SAMPLES
+            } else {
+                0
+            },
+            google_sort: args.get_opt::<GoogleSort>("google_sort")?,
+        };
+        if let Some(lang) = &options.language {
+            if lang.is_empty()
+                || lang.len() > 16
+                || !lang.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            {
+                return Err(HandlerError::field_error(
+                    "lang",
+                    format!("invalid language code: {:?}", lang),
+                ));
+            }
+        }
+        Ok(Self {
+            url,
+            location,
+            options,
+            pagination_delay,
+            max_duration: context.max_scrape_duration,
+            page_cache: context.page_cache.clone(),
+            filter: ReviewFilter::from_query(args)?,
+        })
+    }
+}
+
+// Scrape up to max_reviews reviews matching the request's filter, returning
+// them along with whether or not more reviews were left out, and how many
+// reviews couldn't be parsed.
+async fn collect_reviews<S: Scraper>(
+    mut client: PoolHandle<S>,
+    request: &ReviewRequest,
+    max_reviews: usize,
+    release: &ClientRelease,
+) -> Result<(Vec<Review>, bool, usize), HandlerError> {
+    let started = Instant::now();
+    let results = client
+        .list_reviews(&request.url, &request.location, &request.options)
+        .await;
+    let mut outcome = release_review_client(client, &results, release);
+    let mut it = results?;
+    request.configure(&mut it, started);
+    let mut reviews = Vec::new();
+    while let Some(page) = outcome.check(it.next().await)? {
+        for review in page.into_iter().filter(|r| request.filter.matches(r)) {
+            if reviews.len() >= max_reviews {
+                return Ok((reviews, true, it.skipped_reviews()));
+            }
+            reviews.push(review);
+        }
+    }
+    Ok((reviews, it.hit_deadline(), it.skipped_reviews()))
+}
+
+// Return a client to the pool once a scrape is done with it, recording
+// whether it succeeded.
+fn release_client<S, T>(
+    client: PoolHandle<S>,
+    result: &Result<T, ScrapeError>,
+    release: &ClientRelease,
+) {
+    release
+        .errors
+        .record(result.as_ref().is_err_and(counts_as_failure));
+    return_client(client, result, release);
+}
+
+// Like release_client, but for review listings, which go on fetching pages
+// after their client is back in the pool. The scrape's outcome is recorded
+// once the returned ScrapeOutcome is dropped, so every page should be checked
+// with it.
+fn release_review_client<S>(
+    client: PoolHandle<S>,
+    results: &Result<ReviewIter, ScrapeError>,
+    release: &ClientRelease,
+) -> ScrapeOutcome {
+    let mut outcome = ScrapeOutcome::new(&release.errors);
+    outcome.note(results);
+    return_client(client, results, release);
+    outcome
+}
+
+// In debug mode, a client whose scrape failed is kept out of the pool, leaving
+// its page open for inspection. If Google blocked the scrape, the whole pool
+// pauses.
+fn return_client<S, T>(
+    client: PoolHandle<S>,
+    result: &Result<T, ScrapeError>,
+    release: &ClientRelease,
+) {
+    if result.as_ref().is_err_and(|e| e.is_blocked()) {
+        client.start_cooldown();
+    }
+    if release.debug_browser {
+        if let Err(e) = result {
+            if client.quarantine() {
+                println!("keeping browser open for debugging after error: {}", e);
+            } else {
+                println!("not keeping the last browser open after error: {}", e);
+            }
+        }
+    }
+}
+
+// The accuracy (like altitude_accuracy) is a radius in meters, and falls back
+// to the server's --default-accuracy when omitted.
+fn geo_location(args: &Query, context: &HandlerContext) -> Result<GeoLocation, HandlerError> {
+    let location = GeoLocation {
+        latitude: args.get("latitude")?,
+        longitude: args.get("longitude")?,
+        accuracy: args
+            .get_opt("accuracy")?
+            .unwrap_or(context.default_accuracy),
+        altitude: args.get_opt("altitude")?,
+        altitude_accuracy: args.get_opt("altitude_accuracy")?,
+    };
+    if !(-90.0..=90.0).contains(&location.latitude) {
+        return Err(HandlerError::field_error(
+            "latitude",
+            "latitude must be between -90 and 90",
+        ));
+    }
+    if !(-180.0..=180.0).contains(&location.longitude) {
+        return Err(HandlerError::field_error(
+            "longitude",
+            "longitude must be between -180 and 180",
+        ));
+    }
+    if !(location.accuracy.is_finite() && location.accuracy > 0.0) {
+        return Err(HandlerError::field_error(
+            "accuracy",
+            "accuracy must be a positive number of meters",
+        ));
+    }
+    if location.altitude_accuracy.is_some() && location.altitude.is_none() {
+        return Err(HandlerError::field_error(
+            "altitude_accuracy",
+            "altitude_accuracy requires altitude",
+        ));
+    }
+    Ok(location)
+}
+
+fn review_encoding(args: &Query) -> Result<ReviewEncoding, HandlerError> {
+    Ok(ReviewEncoding {
+        time_format: args
+            .get_opt::<TimeFormat>("time_format")?
+            .unwrap_or(TimeFormat::Epoch),
+        sentiment: args.get_flag("sentiment")?,
+        fields: args.get_opt("fields")?,
+    })
+}
+
+fn max_buffered_reviews(args: &Query) -> Result<usize, HandlerError> {
+    let max_reviews = args
+        .get_opt::<usize>("max_reviews")?
+        .unwrap_or(MAX_BUFFERED_REVIEWS);
+    if max_reviews > MAX_BUFFERED_REVIEWS {
+        Err(HandlerError::field_error(
+            "max_reviews",
+            format!("max_reviews may be at most {}", MAX_BUFFERED_REVIEWS),
+        ))
+    } else {
+        Ok(max_reviews)
+    }
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[derive(Clone)]
+struct ReviewFilter {
+    with_text: bool,
+    since: Option<f64>,
+}
+
+impl ReviewFilter {
+    fn all() -> Self {
+        Self {
+            with_text: false,
+            since: None,
+        }
+    }
+
+    fn from_query(args: &Query) -> Result<Self, HandlerError> {
+        Ok(Self {
+            with_text: args.get_flag("with_text")?,
+            since: args.get_opt("since")?,
+        })
+    }
+
+    fn matches(&self, review: &Review) -> bool {
+        (!self.with_text || !review.content.is_empty())
+            && self.since.map_or(true, |since| review.timestamp > since)
+    }
 }
 
 struct Query {
@@ -148,16 +2136,20 @@ struct Query {
 }
 
 impl Query {
-    fn parse(request: &Request<body::Incoming>) -> Result<Self, HandlerError> {
+    fn parse<B>(request: &Request<B>) -> Result<Self, HandlerError> {
         let query = request
             .uri()
             .query()
-            .ok_or_else(|| HandlerError::QueryError("missing query string".to_owned()))?;
+            .ok_or_else(|| HandlerError::query_error("missing query string"))?;
+        Ok(Self::from_query_string(query))
+    }
+
+    fn from_query_string(query: &str) -> Self {
         let mut value = HashMap::new();
         for (k, v) in url::form_urlencoded::parse(query.as_bytes()) {
             value.insert(k.into(), v.into());
         }
-        Ok(Self { map: value })
+        Self { map: value }
     }
 
     fn get<T: FromStr>(&self, k: &str) -> Result<T, HandlerError>
@@ -166,28 +2158,354 @@ impl Query {
     {
         if let Some(val) = self.map.get(k) {
             T::from_str(val).map_err(|x| {
-                HandlerError::QueryError(format!("failed to parse argument {}: {}", k, x))
+                HandlerError::field_error(
+                    k,
+                    format!(
+                        "failed to parse argument {} as {}: {}",
+                        k,
+                        type_name::<T>(),
+                        x
+                    ),
+                )
             })
         } else {
-            Err(HandlerError::QueryError(format!("no argument: {}", k)))
+            Err(HandlerError::field_error(k, format!("no argument: {}", k)))
+        }
+    }
+
+    fn get_opt<T: FromStr>(&self, k: &str) -> Result<Option<T>, HandlerError>
+    where
+        T::Err: Display,
+    {
+        if self.map.contains_key(k) {
+            self.get(k).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn get_flag(&self, k: &str) -> Result<bool, HandlerError> {
+        match self.map.get(k).map(|x| x.as_str()) {
+            None | Some("") | Some("0") | Some("false") => Ok(false),
+            Some("1") | Some("true") => Ok(true),
+            Some(x) => Err(HandlerError::field_error(
+                k,
+                format!(
+                    "failed to parse argument {}: expected boolean flag but got {:?}",
+                    k, x
+                ),
+            )),
+        }
+    }
+}
+
+// An error which knows how it should be presented by the API.
+pub trait ApiError: Display {
+    fn status(&self) -> StatusCode {
+        StatusCode::OK
+    }
+
+    fn body(&self) -> serde_json::Value {
+        json!({"error": format!("{}", self)})
+    }
+
+    // If set, the client may retry the request after this long.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl ApiError for Infallible {}
+
+impl ApiError for HandlerError {
+    fn status(&self) -> StatusCode {
+        match self {
+            HandlerError::QueryError { .. } => StatusCode::BAD_REQUEST,
+            HandlerError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            HandlerError::Forbidden(_) => StatusCode::FORBIDDEN,
+            HandlerError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            HandlerError::PoolError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::OK,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            HandlerError::PoolError(e) => Some(e.retry_after()),
+            _ => None,
         }
     }
+
+    fn body(&self) -> serde_json::Value {
+        match self {
+            HandlerError::QueryError { field, message } => json!({
+                "error": format!("{}", self),
+                "code": "bad_query",
+                "field": field,
+                "message": message,
+            }),
+            HandlerError::ScrapeError(ScrapeError::UnexpectedRedirect(url)) => json!({
+                "error": format!("{}", self),
+                "code": "unexpected_redirect",
+                "url": url,
+            }),
+            HandlerError::ScrapeError(ScrapeError::TooManyReviews(count, max)) => json!({
+                "error": format!("{}", self),
+                "code": "too_many_reviews",
+                "total_reviews": count,
+                "max_total_reviews": max,
+            }),
+            HandlerError::ScrapeError(ScrapeError::SignInRequired) => json!({
+                "error": format!("{}", self),
+                "code": "sign_in_required",
+                "message": "Google required a sign-in to view this place's reviews",
+            }),
+            HandlerError::ScrapeError(ScrapeError::PhaseError(phase, e)) => json!({
+                "error": format!("{}", self),
+                "code": "scrape_phase_failed",
+                "phase": phase.name(),
+                "selector": phase.selector(),
+                "message": format!("{}", e),
+            }),
+            _ => json!({"error": format!("{}", self)}),
+        }
+    }
+}
+
+// Check for a pretty=1 query argument, asking for indented JSON responses to
+// make the API easier to read while debugging.
+pub fn wants_pretty_json<B>(request: &Request<B>) -> bool {
+    has_query_flag(request, "pretty")
+}
+
+// Check for an envelope=1 query argument, asking for a response to be wrapped
+// in an Envelope so that it can carry warnings.
+pub fn wants_envelope<B>(request: &Request<B>) -> bool {
+    has_query_flag(request, "envelope")
+}
+
+// Check for a stream=1 query argument, asking for results to be streamed as
+// they're found.
+pub fn wants_stream<B>(request: &Request<B>) -> bool {
+    has_query_flag(request, "stream")
+}
+
+fn has_query_flag<B>(request: &Request<B>, name: &str) -> bool {
+    request
+        .uri()
+        .query()
+        .map(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .any(|(k, v)| k == name && (v == "1" || v == "true"))
+        })
+        .unwrap_or_default()
+}
+
+// A successful response along with any caveats about it, such as data which
+// was left out or degraded.
+#[derive(Serialize)]
+pub struct Envelope<T> {
+    pub data: T,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// Respond with the whole Envelope if the request asked for one (see
+// wants_envelope), or otherwise with just its data.
+pub fn envelope_to_response<T: Serialize, E: ApiError>(
+    builder: Builder,
+    result: Result<Envelope<T>, E>,
+    envelope: bool,
+    pretty: bool,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, http::Error> {
+    match result {
+        Ok(x) if !envelope => api_result_to_response(builder, Ok::<_, E>(x.data), pretty),
+        result => api_result_to_response(builder, result, pretty),
+    }
 }
 
-pub fn api_result_to_response<T: Serialize, E: Error + Display>(
+pub fn api_result_to_response<T: Serialize, E: ApiError>(
     builder: Builder,
     result: Result<T, E>,
+    pretty: bool,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>, http::Error> {
     match result {
-        Ok(x) => match serde_json::to_string(&x) {
+        Ok(x) => match to_json(&x, pretty) {
             Ok(x) => builder.body(BoxBody::new(Full::<Bytes>::from(x))),
             Err(x) => builder.status(500).body(BoxBody::new(Full::<Bytes>::from(
-                serde_json::to_string(&json!({"error": format!("failed to encode result: {}", x)}))
-                    .unwrap(),
+                to_json(
+                    &json!({"error": format!("failed to encode result: {}", x)}),
+                    pretty,
+                )
+                .unwrap(),
             ))),
         },
-        Err(x) => builder.body(BoxBody::new(Full::<Bytes>::from(
-            serde_json::to_string(&json!({"error": format!("{}", x)})).unwrap(),
-        ))),
+        Err(x) => {
+            let builder = match x.retry_after() {
+                Some(delay) => builder.header("retry-after", delay.as_secs()),
+                None => builder,
+            };
+            builder
+                .status(x.status())
+                .body(BoxBody::new(Full::<Bytes>::from(
+                    to_json(&x.body(), pretty).unwrap(),
+                )))
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T, pretty: bool) -> serde_json::Result<String> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_scraper::{review, MockScraper};
+
+    fn test_context() -> HandlerContext {
+        HandlerContext {
+            pagination_delay: Duration::ZERO,
+            search_cache: SearchCache::new(Duration::from_secs(600), 10),
+            review_cache: ReviewCache::new(10),
+            page_cache: None,
+            admin_token: None,
+            default_location: None,
+            location_accuracy_floor: 0.0,
+            location_precision: None,
+            default_accuracy: 100.0,
+            in_flight: Default::default(),
+            scrapes: Default::default(),
+            connections: ConnectionLimiter::new(0),
+            place_url_policy: PlaceUrlPolicy {
+                allow_any: true,
+                place_ids: vec![],
+            },
+            release: ClientRelease {
+                debug_browser: false,
+                errors: ErrorRateWindow::new(Duration::from_secs(60), 1.0),
+            },
+            debug: false,
+            max_total_reviews: None,
+            max_scrape_duration: None,
+            merge_concurrency: 1,
+            completeness: CompletenessCheck::new(0.0),
+        }
+    }
+
+    fn place(name: &str, lat: f64, lon: f64) -> LocationInfo {
+        LocationInfo {
+            name: name.to_owned(),
+            url: format!(
+                "https://www.google.com/maps/place/{}/@{},{},15z",
+                name, lat, lon
+            ),
+            extra: vec![],
+            price_level: None,
+            category: None,
+        }
+    }
+
+    fn get(uri: &str) -> Request<()> {
+        Request::builder().uri(uri).body(()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn search_ranks_results_by_distance() {
+        let scraper = MockScraper::new(
+            SearchResult::Multiple(vec![place("Far", 1.0, 1.0), place("Near", 0.0, 0.001)]),
+            vec![],
+        );
+        let pool = ObjectPool::new(vec![scraper.clone()]);
+        let context = test_context();
+        let request = get("/api/search?query=cafe&latitude=0&longitude=0");
+        let (search_id, response) = handle_search(pool, request, &context).await.unwrap();
+        let SearchResponse::List(results) = response else {
+            panic!("expected a list of results");
+        };
+        let names: Vec<_> = results.iter().map(|x| x.info.name.as_str()).collect();
+        assert_eq!(names, vec!["Near", "Far"]);
+        assert_eq!(
+            context.search_cache.resolve(&search_id, 1),
+            Some(results[1].info.url.clone())
+        );
+        assert_eq!(scraper.calls(), vec!["search cafe @0,0"]);
+    }
+
+    #[tokio::test]
+    async fn reviews_are_filtered_and_limited() {
+        let scraper = MockScraper::new(
+            SearchResult::NotFound,
+            vec![
+                review("a", 300.0, 5.0, "Great"),
+                review("b", 200.0, 1.0, ""),
+                review("c", 100.0, 3.0, "Fine"),
+                review("d", 50.0, 4.0, "Good"),
+            ],
+        );
+        let pool = ObjectPool::new(vec![scraper]);
+        let context = test_context();
+        let request = get(
+            "/api/reviews?url=https://www.google.com/maps/place/A&latitude=0&longitude=0\
+             &format=json&with_text=1&max_reviews=2",
+        );
+        let response = handle_reviews(pool, request, &context).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let ids: Vec<_> = body["reviews"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["review_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(body["truncated"], true);
+    }
+
+    #[tokio::test]
+    async fn json_reviews_fail_before_output_with_an_error_response() {
+        let mut scraper = MockScraper::new(SearchResult::NotFound, vec![]);
+        scraper.fail_reviews = true;
+        let pool = ObjectPool::new(vec![scraper]);
+        let context = test_context();
+        let uri = "/api/reviews?url=https://www.google.com/maps/place/A&latitude=0&longitude=0";
+
+        let result =
+            handle_reviews(pool.clone(), get(&format!("{}&format=json", uri)), &context).await;
+        assert!(matches!(result, Err(HandlerError::ScrapeError(_))));
+
+        // Streamed formats have already started their responses, so the error
+        // ends the body instead.
+        let response = handle_reviews(pool, get(uri), &context).await.unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let line: serde_json::Value =
+            serde_json::from_slice(body.split(|x| *x == b'\n').next().unwrap()).unwrap();
+        assert!(line["error"].is_string());
+    }
+
+    #[test]
+    fn merged_reviews_without_ids_are_compared_by_content() {
+        let previous = CachedReviews {
+            reviews: vec![
+                review("a", 200.0, 5.0, "Great"),
+                review("", 100.0, 4.0, "Good"),
+                review("", 50.0, 2.0, "Meh"),
+            ],
+            truncated: false,
+        };
+        let merged = merge_reviews(
+            vec![
+                review("", 300.0, 3.0, "Fine"),
+                review("a", 200.0, 5.0, "Great"),
+                review("", 100.0, 4.0, "Good"),
+            ],
+            &previous,
+        );
+        let contents: Vec<_> = merged.reviews.iter().map(|x| x.content.as_str()).collect();
+        assert_eq!(contents, vec!["Fine", "Great", "Good", "Meh"]);
     }
 }
@@ -1,7 +1,11 @@
+// arguments[0] is a list of prefixes of the "can't find" message, one for each
+// supported locale.
+const notFoundTexts = arguments[0];
 const divs = document.getElementsByTagName('div');
 for (let i = 0; i < divs.length; i++) {
-    if (divs[i].textContent.startsWith("Google Maps can't find")) {
+    const text = divs[i].textContent;
+    if (notFoundTexts.some((x) => text.startsWith(x))) {
         return true;
     }
 }
-return false;
\ No newline at end of file
+return false;
@@ -0,0 +1,14 @@
+// The page header always links to the sign-in page, so only look for sign-in
+// links inside the place panel, where Google puts its prompt when it hides
+// the reviews.
+const panels = document.querySelectorAll('[role="main"]');
+for (let i = 0; i < panels.length; i++) {
+    const links = panels[i].getElementsByTagName('a');
+    for (let j = 0; j < links.length; j++) {
+        const href = links[j].getAttribute('href') || '';
+        if (href.includes('accounts.google.com')) {
+            return true;
+        }
+    }
+}
+return false;
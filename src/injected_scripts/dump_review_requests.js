@@ -1,23 +1,46 @@
-const origOpen = XMLHttpRequest.prototype.open;
-XMLHttpRequest.prototype.open = function (method, url) {
-    this._url = url;
-    return origOpen.apply(this, arguments);
-};
-const origSend = XMLHttpRequest.prototype.send;
+const patchFetch = arguments[0];
 window.recordedReviewResponses = [];
-XMLHttpRequest.prototype.send = function () {
-    const oldCb = this.onreadystatechange;
-    this.onreadystatechange = function () {
-        if (this.readyState == 4 && this._url.includes('listugcposts')) {
-            let url = this._url;
+// The page may already have been patched by a previous scrape, in which case
+// wrapping again would record every response more than once.
+if (!XMLHttpRequest.prototype._reviewRecorderPatched) {
+    XMLHttpRequest.prototype._reviewRecorderPatched = true;
+    const origOpen = XMLHttpRequest.prototype.open;
+    XMLHttpRequest.prototype.open = function (method, url) {
+        this._url = url;
+        return origOpen.apply(this, arguments);
+    };
+    const origSend = XMLHttpRequest.prototype.send;
+    XMLHttpRequest.prototype.send = function () {
+        const oldCb = this.onreadystatechange;
+        this.onreadystatechange = function () {
+            if (this.readyState == 4 && this._url.includes('listugcposts')) {
+                let url = this._url;
+                if (url.startsWith('/')) {
+                    url = location.origin + url;
+                }
+                window.recordedReviewResponses.push([url, this.response]);
+            }
+            if (oldCb) {
+                return oldCb.apply(this, arguments);
+            }
+        };
+        origSend.apply(this, arguments);
+    };
+}
+if (patchFetch && !window.fetch._reviewRecorderPatched) {
+    const origFetch = window.fetch;
+    window.fetch = function (input) {
+        const result = origFetch.apply(this, arguments);
+        let url = (input instanceof Request) ? input.url : String(input);
+        if (url.includes('listugcposts')) {
             if (url.startsWith('/')) {
                 url = location.origin + url;
             }
-            window.recordedReviewResponses.push([url, this.response]);
-        }
-        if (oldCb) {
-            return oldCb.apply(this, arguments);
+            result.then((resp) => resp.clone().text()).then((text) => {
+                window.recordedReviewResponses.push([url, text]);
+            }).catch(() => null);
         }
+        return result;
     };
-    origSend.apply(this, arguments);
-};
\ No newline at end of file
+    window.fetch._reviewRecorderPatched = true;
+}
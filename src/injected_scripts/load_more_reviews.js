@@ -0,0 +1,22 @@
+// Nudge a review panel which only loads reviews as it's scrolled, unless a
+// review request was already recorded. Returns true if one was.
+if ((window.recordedReviewResponses || []).length) {
+    return true;
+}
+const buttons = Array.from(document.getElementsByTagName('button')).filter((x) => {
+    const attr = x.getAttribute('jsaction');
+    return attr && attr.endsWith('reviewChart.moreReviews');
+});
+if (buttons.length) {
+    buttons[0].click();
+}
+// Scroll whichever element holds the review cards to the bottom.
+const cards = document.querySelectorAll('[data-review-id]');
+let panel = cards.length ? cards[cards.length - 1].parentElement : null;
+while (panel && panel.scrollHeight <= panel.clientHeight) {
+    panel = panel.parentElement;
+}
+if (panel) {
+    panel.scrollTop = panel.scrollHeight;
+}
+return false;
@@ -0,0 +1,33 @@
+const limit = arguments[0];
+const results = [];
+const seen = new Set();
+
+function addUrl(url) {
+    if (!url || results.length >= limit || seen.has(url)) {
+        return;
+    }
+    if (url.startsWith('https://') && url.includes('googleusercontent.com')) {
+        seen.add(url);
+        results.push(url);
+    }
+}
+
+// Only look inside the main place panel so we don't pick up avatars from
+// elsewhere on the page.
+const main = document.querySelector('[role="main"]') || document.body;
+
+const images = main.getElementsByTagName('img');
+for (let i = 0; i < images.length; i++) {
+    addUrl(images[i].src);
+}
+
+// Some gallery entries are rendered as background images.
+const styled = main.querySelectorAll('[style*="background-image"]');
+for (let i = 0; i < styled.length; i++) {
+    const match = /url\(["']?([^"')]+)["']?\)/.exec(styled[i].style.backgroundImage);
+    if (match) {
+        addUrl(match[1]);
+    }
+}
+
+return results;
@@ -0,0 +1,29 @@
+// Returns [category, priceLabel] from the place panel, where either may be
+// null if the panel doesn't show it.
+const main = document.querySelector('[role="main"]');
+if (!main) {
+    return [null, null];
+}
+
+let category = null;
+const categoryButton = main.querySelector('button[jsaction*="category"]');
+if (categoryButton) {
+    category = categoryButton.textContent.trim() || null;
+}
+
+// The price is shown as a run of currency symbols, either on its own or
+// inside an element labeled like "Price: Moderate".
+let price = null;
+const spans = main.getElementsByTagName('span');
+for (let i = 0; i < spans.length; i++) {
+    const span = spans[i];
+    if (span.getElementsByTagName('span').length) {
+        continue;
+    }
+    const text = span.textContent.trim();
+    if (/^([$€£¥₩₹])\1{0,3}$/.test(text)) {
+        price = text;
+        break;
+    }
+}
+return [category, price];
@@ -0,0 +1,22 @@
+// Returns [ratingLabel, reviewCountLabel], where either may be null, or null
+// if the place panel hasn't loaded yet.
+const main = document.querySelector('[role="main"]');
+if (!main) {
+    return null;
+}
+
+let rating = null;
+let count = null;
+const labeled = main.querySelectorAll('[aria-label]');
+for (let i = 0; i < labeled.length; i++) {
+    const label = labeled[i].getAttribute('aria-label').trim();
+    if (rating === null && /^[\d.,]+ stars?\b/i.test(label)) {
+        rating = label;
+    } else if (count === null && /^[\d.,]+ reviews?$/i.test(label)) {
+        count = label;
+    }
+}
+if (rating === null && count === null) {
+    return null;
+}
+return [rating, count];
@@ -0,0 +1,37 @@
+// Read the review cards rendered on the page, for when no review requests
+// were recorded. Returns [id, author, ratingLabel, text, dateLabel] entries.
+const results = [];
+const cards = document.querySelectorAll('[data-review-id]');
+const seen = new Set();
+for (let i = 0; i < cards.length; i++) {
+    const card = cards[i];
+    const id = card.getAttribute('data-review-id');
+    // Nested elements repeat the review ID, so only use the outermost.
+    if (seen.has(id)) {
+        continue;
+    }
+    seen.add(id);
+    const stars = card.querySelector('[role="img"][aria-label*="star"]');
+    if (!stars) {
+        continue;
+    }
+    let author = card.getAttribute('aria-label') || '';
+    const authorButton = card.querySelector('button[data-href*="/contrib/"] div');
+    if (!author && authorButton) {
+        author = authorButton.textContent;
+    }
+    let text = '';
+    const textElement = card.querySelector('[lang] span, .wiI7pd');
+    if (textElement) {
+        text = textElement.textContent;
+    }
+    let date = '';
+    const spans = stars.parentElement ? stars.parentElement.querySelectorAll('span') : [];
+    for (let j = 0; j < spans.length; j++) {
+        if (/\bago$/.test(spans[j].textContent.trim())) {
+            date = spans[j].textContent.trim();
+        }
+    }
+    results.push([id, author.trim(), stars.getAttribute('aria-label'), text.trim(), date]);
+}
+return results;
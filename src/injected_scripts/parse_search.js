@@ -1,14 +1,26 @@
+// arguments[0] is a list of prefixes of the results list's label, one for each
+// supported locale. The list is also recognized by its feed role, which
+// doesn't depend on the locale.
+const resultsLabels = arguments[0];
 const divs = document.getElementsByTagName('div');
 const results = [];
+// A nested list could match as well as its parent, so skip repeated links.
+const seen = new Set();
 for (let i = 0; i < divs.length; i++) {
     const div = divs[i];
-    if ((div.getAttribute('aria-label') || '').startsWith('Results for')) {
+    const label = div.getAttribute('aria-label') || '';
+    const isFeed = div.getAttribute('role') == 'feed' && label;
+    if (isFeed || resultsLabels.some((x) => label.startsWith(x))) {
         const links = div.getElementsByTagName('a');
         for (let j = 0; j < links.length; j++) {
             const link = links[j];
             const href = link.href;
             const name = link.getAttribute('aria-label');
             if (href && name && href.startsWith('https://www.google.com/maps/place')) {
+                if (seen.has(href)) {
+                    continue;
+                }
+                seen.add(href);
                 const lines = [];
                 const parent = link.parentElement;
 
@@ -0,0 +1,42 @@
+const lang = arguments[0].toLowerCase();
+const names = [lang];
+try {
+    const displayNames = new Intl.DisplayNames([navigator.language || 'en'], { type: 'language' });
+    const name = displayNames.of(lang);
+    if (name) {
+        names.push(name.toLowerCase());
+    }
+} catch (e) {
+    // Unknown language code; we can still match on the raw code.
+}
+
+function findOption() {
+    const options = Array.from(document.querySelectorAll(
+        '[role="menuitemradio"], [role="radio"], [role="option"], button',
+    ));
+    return options.find((x) => {
+        const label = (x.getAttribute('aria-label') || x.textContent || '').trim().toLowerCase();
+        return names.includes(label);
+    });
+}
+
+let option = findOption();
+if (!option) {
+    // The language choices may be hidden behind a menu button.
+    const menuButton = Array.from(document.getElementsByTagName('button')).find((x) => {
+        return (x.getAttribute('aria-label') || '').toLowerCase().includes('language');
+    });
+    if (!menuButton) {
+        return false;
+    }
+    menuButton.click();
+    option = findOption();
+    if (!option) {
+        return false;
+    }
+}
+
+// Only responses for the filtered reviews should be recorded from now on.
+window.recordedReviewResponses = [];
+option.click();
+return true;
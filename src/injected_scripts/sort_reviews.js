@@ -0,0 +1,32 @@
+// arguments[0] is a list of lowercase labels for the sort option to select,
+// such as ["newest"].
+const labels = arguments[0];
+
+function findOption() {
+    const options = Array.from(document.querySelectorAll('[role="menuitemradio"]'));
+    return options.find((x) => {
+        const label = (x.getAttribute('aria-label') || x.textContent || '').trim().toLowerCase();
+        return labels.includes(label);
+    });
+}
+
+let option = findOption();
+if (!option) {
+    const menuButton = Array.from(document.getElementsByTagName('button')).find((x) => {
+        const label = (x.getAttribute('aria-label') || x.textContent || '').toLowerCase();
+        return label.includes('sort');
+    });
+    if (!menuButton) {
+        return false;
+    }
+    menuButton.click();
+    option = findOption();
+    if (!option) {
+        return false;
+    }
+}
+
+// Only responses for the sorted reviews should be recorded from now on.
+window.recordedReviewResponses = [];
+option.click();
+return true;
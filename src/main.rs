@@ -1,23 +1,63 @@
-use std::{convert::Infallible, error::Error, sync::Arc};
+use std::{
+    convert::Infallible,
+    error::Error,
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
 use clap::Parser;
-use futures::{pin_mut, select, FutureExt};
+use futures::{future::select_all, pin_mut, select, FutureExt};
 use http::response::Builder;
 use http_body_util::{combinators::BoxBody, Full};
 use hyper::{body, server::conn::http1, service::service_fn, Request, Response};
 
+mod access_log;
+mod calendar;
 mod client;
 mod client_pool;
+mod connection_limit;
 mod geolocate;
 mod handlers;
-use client::Client;
-use client_pool::{new_client_pool, ObjectPool};
-use handlers::{api_result_to_response, handle_reviews, handle_search};
+#[cfg(test)]
+mod mock_scraper;
+mod output;
+mod replay;
+mod review_cache;
+mod scrape_registry;
+mod search_cache;
+mod sentiment;
+mod stats;
+use client::{
+    configure_pagination_client, remove_stale_user_data_dirs, CaptureStrategy, Client,
+    ClientOptions, HttpVersion, PaginationClientOptions, Scraper, DEFAULT_NOT_FOUND_TEXTS,
+    DEFAULT_RESULTS_LABELS,
+};
+use client_pool::{new_client_pool, new_warming_client_pool, spawn_idle_recycler, ObjectPool};
+use handlers::{
+    api_result_to_response, envelope_to_response, handle_admin_recycle, handle_cancel,
+    handle_location, handle_locations, handle_merged_reviews, handle_photos, handle_rating,
+    handle_review_distribution, handle_review_summary, handle_reviews, handle_reviews_by_rating,
+    handle_reviews_ws, handle_search, handle_search_stream, wants_envelope, wants_pretty_json,
+    wants_stream, ClientRelease, CompletenessCheck, Envelope, ErrorRateWindow, HandlerContext,
+    HealthStatus, LocationSource, PlaceUrlPolicy, PoolStatus, VersionInfo,
+};
 use hyper_util::rt::{TokioIo, TokioTimer};
-use tokio::{net::TcpListener, signal};
+use socket2::{SockRef, TcpKeepalive};
+use thirtyfour::error::WebDriverResult;
+use tokio::{
+    net::{lookup_host, TcpListener, TcpSocket},
+    signal,
+};
 
-use crate::geolocate::IpLocator;
+use crate::access_log::{AccessEntry, AccessLog, AccessLogFormat};
+use crate::connection_limit::ConnectionLimiter;
+use crate::geolocate::{IpLocator, IP_LOCATION_ACCURACY_M};
+use crate::replay::{Recorder, ReplayScraper};
+use crate::review_cache::{PageCache, ReviewCache};
+use crate::search_cache::SearchCache;
 
 const PAGE_MAPPING: [(&'static str, &'static str); 24] = [
     ("", include_str!("assets/index.html")),
@@ -58,6 +98,28 @@ const PAGE_MAPPING: [(&'static str, &'static str); 24] = [
     ("/css/404.css", include_str!("assets/css/404.css")),
 ];
 
+// The (path, content type, content) of every static page.
+fn static_routes() -> impl Iterator<Item = (&'static str, &'static str, &'static str)> {
+    PAGE_MAPPING.into_iter().map(|(path, content)| {
+        let content_type = static_content_type(path).unwrap_or("application/octet-stream");
+        (path, content_type, content)
+    })
+}
+
+fn static_content_type(path: &str) -> Option<&'static str> {
+    if path.is_empty() || path.ends_with('/') {
+        return Some("text/html");
+    }
+    match path.rsplit_once('.')?.1 {
+        "html" => Some("text/html"),
+        "css" => Some("text/css"),
+        "js" => Some("application/javascript"),
+        "map" => Some("application/json"),
+        "ts" => Some("text/plain"),
+        _ => None,
+    }
+}
+
 #[derive(Parser, Clone)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
@@ -65,19 +127,305 @@ struct Args {
     driver: String,
 
     #[clap(long, value_parser, default_value = "0.0.0.0:8080")]
-    host: String,
+    host: Vec<String>,
 
     #[clap(long, value_parser, default_value_t = 0)]
     num_proxies: usize,
 
+    #[clap(long, value_parser, default_value_t = 1)]
+    pool_size: usize,
+
+    #[clap(long, value_parser, default_value_t = 4)]
+    startup_concurrency: usize,
+
+    #[clap(long, action)]
+    serve_while_warming: bool,
+
+    #[clap(long, action)]
+    reject_while_warming: bool,
+
     #[clap(long, short, action)]
     headless: bool,
+
+    #[clap(long, action)]
+    debug_browser: bool,
+
+    #[clap(long, action)]
+    debug: bool,
+
+    #[clap(long, action)]
+    strict_location: bool,
+
+    #[clap(long, value_parser, default_value_t = 0.0)]
+    pagination_delay: f64,
+
+    #[clap(long, value_parser)]
+    max_client_idle: Option<f64>,
+
+    #[clap(long, value_parser, default_value_t = 0.0)]
+    min_client_interval: f64,
+
+    #[clap(long, value_parser)]
+    max_waiting_requests: Option<usize>,
+
+    #[clap(long, value_parser, default_value_t = 60.0)]
+    block_cooldown: f64,
+
+    #[clap(long, value_parser)]
+    max_total_reviews: Option<u64>,
+
+    #[clap(long, value_parser)]
+    max_scrape_duration: Option<f64>,
+
+    #[clap(long, value_parser, default_value_t = 1)]
+    search_retries: usize,
+
+    #[clap(long, value_parser, default_value_t = 0)]
+    search_detail_concurrency: usize,
+
+    #[clap(long, value_parser, default_value_t = 1)]
+    more_reviews_clicks: usize,
+
+    #[clap(long, value_parser)]
+    results_label: Vec<String>,
+
+    #[clap(long, value_parser)]
+    not_found_text: Vec<String>,
+
+    #[clap(long, value_parser, default_value_t = 100)]
+    review_cache_size: usize,
+
+    #[clap(long, value_parser, default_value_t = 0)]
+    page_cache_size: usize,
+
+    #[clap(long, value_parser, default_value_t = 60.0)]
+    page_cache_ttl: f64,
+
+    #[clap(long, value_parser, default_value = "xhr-fetch")]
+    capture_strategy: CaptureStrategy,
+
+    #[clap(long, value_parser, default_value = "https://www.google.com/maps")]
+    maps_base_url: String,
+
+    #[clap(long, value_parser)]
+    chrome_binary: Option<String>,
+
+    #[clap(long, value_parser)]
+    chrome_arg: Vec<String>,
+
+    #[clap(long, value_parser)]
+    user_data_dir_base: Option<PathBuf>,
+
+    #[clap(long, value_parser)]
+    persistent_profile: Option<PathBuf>,
+
+    #[clap(long, value_parser, default_value_t = 1024)]
+    listen_backlog: u32,
+
+    #[clap(long, value_parser, default_value_t = 256)]
+    max_connections_per_ip: usize,
+
+    #[clap(long, value_parser)]
+    tcp_keepalive: Option<f64>,
+
+    #[clap(long, value_parser)]
+    tcp_keepalive_interval: Option<f64>,
+
+    #[clap(long, value_parser, default_value_t = 10.0)]
+    header_read_timeout: f64,
+
+    #[clap(long, value_parser, default_value_t = 64 * 1024)]
+    max_request_buf_size: usize,
+
+    #[clap(long, action)]
+    disable_http_keep_alive: bool,
+
+    #[clap(long, value_parser)]
+    admin_token: Option<String>,
+
+    #[clap(long, value_parser)]
+    default_location: Option<String>,
+
+    #[clap(long, value_parser)]
+    fine_location_db: Option<PathBuf>,
+
+    #[clap(long, value_parser, default_value_t = IP_LOCATION_ACCURACY_M)]
+    location_accuracy_floor: f64,
+
+    #[clap(long, value_parser)]
+    location_precision: Option<f64>,
+
+    #[clap(long, value_parser, default_value_t = 100.0)]
+    default_accuracy: f64,
+
+    #[clap(long, value_parser, default_value_t = 2)]
+    merge_concurrency: usize,
+
+    #[clap(long, value_parser, default_value_t = 0.5)]
+    min_scrape_completeness: f64,
+
+    #[clap(long, value_parser, default_value_t = 300.0)]
+    error_rate_window: f64,
+
+    #[clap(long, value_parser, default_value_t = 0.5)]
+    max_error_rate: f64,
+
+    #[clap(long, action)]
+    allow_any_review_url: bool,
+
+    #[clap(long, value_parser)]
+    allowed_place: Vec<String>,
+
+    #[clap(long, value_parser, default_value = "off")]
+    access_log: AccessLogFormat,
+
+    #[clap(long, value_parser)]
+    access_log_file: Option<String>,
+
+    #[clap(long, value_parser)]
+    record_scrapes: Option<PathBuf>,
+
+    #[clap(long, value_parser)]
+    replay_scrapes: Option<PathBuf>,
+
+    #[clap(long, value_parser)]
+    pagination_ca_cert: Vec<PathBuf>,
+
+    #[clap(long, action)]
+    pagination_accept_invalid_certs: bool,
+
+    #[clap(long, value_parser, default_value = "auto")]
+    pagination_http_version: HttpVersion,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let args = Args::parse();
-    let pool = new_client_pool(1, &args.driver, args.headless).await?;
+    if args.pool_size == 0 {
+        return Err("--pool-size must be at least 1".into());
+    }
+    if args.debug_browser && args.headless {
+        return Err("--debug-browser cannot be combined with --headless".into());
+    }
+    if args.persistent_profile.is_some() && args.user_data_dir_base.is_some() {
+        return Err("--persistent-profile cannot be combined with --user-data-dir-base".into());
+    }
+    if args.reject_while_warming && !args.serve_while_warming {
+        return Err("--reject-while-warming requires --serve-while-warming".into());
+    }
+    if args.access_log_file.is_some() && args.access_log == AccessLogFormat::Off {
+        return Err("--access-log-file requires --access-log".into());
+    }
+    if args.record_scrapes.is_some() && args.replay_scrapes.is_some() {
+        return Err("--record-scrapes cannot be combined with --replay-scrapes".into());
+    }
+    if args
+        .location_precision
+        .is_some_and(|x| !(x.is_finite() && x > 0.0))
+    {
+        return Err("--location-precision must be a positive number of degrees".into());
+    }
+    if !(0.0..=1.0).contains(&args.min_scrape_completeness) {
+        return Err("--min-scrape-completeness must be between 0 and 1".into());
+    }
+    if !(0.0..=1.0).contains(&args.max_error_rate) {
+        return Err("--max-error-rate must be between 0 and 1".into());
+    }
+    if args.more_reviews_clicks == 0 {
+        return Err("--more-reviews-clicks must be at least 1".into());
+    }
+    if args.merge_concurrency == 0 {
+        return Err("--merge-concurrency must be at least 1".into());
+    }
+    if !(args.default_accuracy.is_finite() && args.default_accuracy > 0.0) {
+        return Err("--default-accuracy must be a positive number of meters".into());
+    }
+    if args.pagination_accept_invalid_certs {
+        println!("warning: accepting invalid TLS certificates when fetching review pages");
+    }
+    configure_pagination_client(&PaginationClientOptions {
+        root_certs: args.pagination_ca_cert.clone(),
+        accept_invalid_certs: args.pagination_accept_invalid_certs,
+        http_version: args.pagination_http_version,
+    })?;
+    let max_client_idle = args
+        .max_client_idle
+        .map(Duration::try_from_secs_f64)
+        .transpose()?;
+    let min_client_interval = Duration::try_from_secs_f64(args.min_client_interval)?;
+    let client_options = ClientOptions {
+        driver: args.driver.clone(),
+        headless: args.headless,
+        search_retries: args.search_retries,
+        capture_strategy: args.capture_strategy,
+        maps_base_url: args.maps_base_url.clone(),
+        chrome_binary: args.chrome_binary.clone(),
+        chrome_args: args.chrome_arg.clone(),
+        user_data_base: args.user_data_dir_base.clone(),
+        persistent_profile: args.persistent_profile.clone(),
+        detail_concurrency: args.search_detail_concurrency,
+        strict_location: args.strict_location,
+        more_reviews_clicks: args.more_reviews_clicks,
+        results_labels: with_defaults(DEFAULT_RESULTS_LABELS, &args.results_label),
+        not_found_texts: with_defaults(DEFAULT_NOT_FOUND_TEXTS, &args.not_found_text),
+    };
+    if let Some(path) = &args.replay_scrapes {
+        // Replaying doesn't need a browser, so don't check for a driver.
+        let scraper = ReplayScraper::load(path, &client_options)?;
+        let pool = ObjectPool::new(vec![scraper; args.pool_size]);
+        return serve(args, pool, max_client_idle, min_client_interval).await;
+    }
+    check_driver(&args.driver).await?;
+    if let Some(base) = &args.user_data_dir_base {
+        remove_stale_user_data_dirs(base)?;
+    }
+    let recorder = match &args.record_scrapes {
+        Some(path) => Some(Arc::new(Recorder::create(path)?)),
+        None => None,
+    };
+    let pool = start_client_pool(&args, &client_options, move |mut x| {
+        if let Some(recorder) = &recorder {
+            x.set_recorder(recorder.clone());
+        }
+        x
+    })
+    .await?;
+    serve(args, pool, max_client_idle, min_client_interval).await
+}
+
+// Start the browsers, either before serving or in the background if
+// --serve-while-warming is set.
+async fn start_client_pool<S, F>(
+    args: &Args,
+    options: &ClientOptions,
+    wrap: F,
+) -> WebDriverResult<ObjectPool<S>>
+where
+    S: Scraper + Send + 'static,
+    F: Fn(Client) -> S + Send + 'static,
+{
+    if args.serve_while_warming {
+        let pool = new_warming_client_pool(args.pool_size, args.startup_concurrency, options, wrap);
+        pool.set_reject_while_warming(args.reject_while_warming);
+        Ok(pool)
+    } else {
+        new_client_pool(args.pool_size, args.startup_concurrency, options, wrap).await
+    }
+}
+
+// Run the server until it's interrupted, and then close the pool.
+async fn serve<S: Scraper>(
+    args: Args,
+    pool: ObjectPool<S>,
+    max_client_idle: Option<Duration>,
+    min_client_interval: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    pool.set_min_interval(min_client_interval);
+    pool.set_max_waiting(args.max_waiting_requests);
+    pool.set_block_cooldown(Duration::try_from_secs_f64(args.block_cooldown)?);
+    if let Some(max_idle) = max_client_idle {
+        spawn_idle_recycler(pool.clone(), max_idle);
+    }
     let result = entrypoint(args, &pool).await;
 
     pool.close(|client| client.close()).await?;
@@ -85,79 +433,144 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     result
 }
 
-async fn entrypoint(
+async fn entrypoint<S: Scraper>(
     args: Args,
-    pool: &ObjectPool<Client>,
+    pool: &ObjectPool<S>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let locator = Arc::new(IpLocator::new(args.num_proxies));
-    let listener = TcpListener::bind(&args.host).await?;
+    let mut locator = IpLocator::new(args.num_proxies);
+    if let Some(path) = &args.fine_location_db {
+        locator.load_fine_db(path)?;
+    }
+    let locator = Arc::new(locator);
+    let context = Arc::new(HandlerContext {
+        pagination_delay: Duration::try_from_secs_f64(args.pagination_delay)?,
+        search_cache: SearchCache::new(Duration::from_secs(600), 1000),
+        review_cache: ReviewCache::new(args.review_cache_size),
+        page_cache: if args.page_cache_size > 0 {
+            Some(Arc::new(PageCache::new(
+                args.page_cache_size,
+                Duration::try_from_secs_f64(args.page_cache_ttl)?,
+            )))
+        } else {
+            None
+        },
+        admin_token: args.admin_token.clone(),
+        default_location: args
+            .default_location
+            .as_deref()
+            .map(parse_location)
+            .transpose()?,
+        location_accuracy_floor: args.location_accuracy_floor,
+        location_precision: args.location_precision,
+        default_accuracy: args.default_accuracy,
+        merge_concurrency: args.merge_concurrency,
+        completeness: CompletenessCheck::new(args.min_scrape_completeness),
+        in_flight: Default::default(),
+        scrapes: Default::default(),
+        connections: ConnectionLimiter::new(args.max_connections_per_ip),
+        place_url_policy: PlaceUrlPolicy {
+            allow_any: args.allow_any_review_url,
+            place_ids: args.allowed_place.clone(),
+        },
+        release: ClientRelease {
+            debug_browser: args.debug_browser,
+            errors: ErrorRateWindow::new(
+                Duration::try_from_secs_f64(args.error_rate_window)?,
+                args.max_error_rate,
+            ),
+        },
+        debug: args.debug,
+        max_total_reviews: args.max_total_reviews,
+        max_scrape_duration: args
+            .max_scrape_duration
+            .map(Duration::try_from_secs_f64)
+            .transpose()?,
+    });
+    let version_info = Arc::new(VersionInfo::new(
+        &args.driver,
+        args.pool_size,
+        args.headless,
+    ));
+    let access_log = AccessLog::new(args.access_log, args.access_log_file.as_deref())?;
+    let keepalive = tcp_keepalive_config(&args)?;
+    let http_builder = http1_builder_config(&args)?;
+    let mut listeners = Vec::new();
+    for host in &args.host {
+        listeners.push(bind_listener(host, args.listen_backlog).await?);
+    }
     let exit_signal = signal::ctrl_c().fuse();
     pin_mut!(exit_signal);
     loop {
         let tcp;
-        let accept = listener.accept().fuse();
+        // Accepting is cancel-safe, so we can wait on every listener at once
+        // and drop the accepts which didn't complete.
+        let accept = select_all(listeners.iter().map(|x| Box::pin(x.accept()))).fuse();
         pin_mut!(accept);
         select! {
-            x = accept => tcp = x?.0,
+            x = accept => tcp = x.0?.0,
             _ = exit_signal => {
                 println!("Got interrupt; stopping server.");
                 return Ok(());
             }
         }
+        if let Some(keepalive) = &keepalive {
+            if let Err(e) = SockRef::from(&tcp).set_tcp_keepalive(keepalive) {
+                println!("Error enabling TCP keepalive: {}", e);
+            }
+        }
         let client_ip = format!("{}", tcp.peer_addr().expect("get peer address").ip());
+        // Dropping the stream closes a connection over the limit.
+        let Some(open_connection) = context.connections.try_open(&client_ip) else {
+            continue;
+        };
         let io = TokioIo::new(tcp);
 
         let local_pool = pool.clone();
         let local_locator = locator.clone();
+        let local_version_info = version_info.clone();
+        let local_context = context.clone();
+        let local_access_log = access_log.clone();
+        let local_http_builder = http_builder.clone();
 
         let make_service = service_fn(move |req: Request<body::Incoming>| {
             let pool = local_pool.clone();
             let local_locator = local_locator.clone();
             let local_client_ip = client_ip.clone();
+            let local_version_info = local_version_info.clone();
+            let context = local_context.clone();
+            let access_log = local_access_log.clone();
             async move {
-                if req.uri().path() == "/api/search" {
-                    let result = handle_search(pool, req).await;
-                    api_result_to_response(Response::builder(), result)
-                } else if req.uri().path() == "/api/reviews" {
-                    match handle_reviews(pool, req).await {
-                        Err(e) => {
-                            api_result_to_response(Response::builder(), Result::<String, _>::Err(e))
-                        }
-                        Ok(x) => Ok(x),
-                    }
-                } else if req.uri().path() == "/api/location" {
-                    let location = local_locator.lookup_for_request(&req, &local_client_ip);
-                    api_result_to_response(
-                        Response::builder(),
-                        Result::<Option<(f64, f64)>, Infallible>::Ok(location),
-                    )
-                } else {
-                    for (page, content) in PAGE_MAPPING {
-                        if req.uri().path() == page {
-                            let content_type = match page.split(".").last().unwrap() {
-                                "css" => "text/css",
-                                "/" | "html" => "text/html",
-                                "js" => "application/javascript",
-                                _ => "text/plain",
-                            };
-                            return Ok(static_response(
-                                Response::builder().header("content-type", content_type),
-                                content,
-                            )?);
-                        }
+                let entry = AccessEntry {
+                    method: req.method().clone(),
+                    path: req.uri().path().to_owned(),
+                    client_ip: local_locator.request_ip(&req, &local_client_ip),
+                    start: Instant::now(),
+                };
+                let response = route(
+                    req,
+                    pool,
+                    local_locator,
+                    local_client_ip,
+                    local_version_info,
+                    context,
+                )
+                .await;
+                match (access_log, response) {
+                    (Some(log), Ok(response)) => Ok(log.wrap(entry, response)),
+                    (Some(log), Err(e)) => {
+                        log.write(&entry, 500, 0);
+                        Err(e)
                     }
-                    Ok(static_response(
-                        Response::builder().status(404),
-                        include_str!("assets/404.html"),
-                    )?)
+                    (None, response) => response,
                 }
             }
         });
 
         tokio::task::spawn(async move {
-            if let Err(err) = http1::Builder::new()
-                .timer(TokioTimer::new())
+            let _open_connection = open_connection;
+            if let Err(err) = local_http_builder
                 .serve_connection(io, make_service)
+                .with_upgrades()
                 .await
             {
                 println!("Error serving connection: {:?}", err);
@@ -166,9 +579,294 @@ async fn entrypoint(
     }
 }
 
+async fn route<S: Scraper>(
+    req: Request<body::Incoming>,
+    pool: ObjectPool<S>,
+    locator: Arc<IpLocator>,
+    client_ip: String,
+    version_info: Arc<VersionInfo>,
+    context: Arc<HandlerContext>,
+) -> Result<Response<BoxBody<Bytes, Infallible>>, http::Error> {
+    let pretty = wants_pretty_json(&req);
+    if req.uri().path() == "/api/search" && wants_stream(&req) {
+        match handle_search_stream(pool, req, context).await {
+            Err(e) => api_result_to_response(Response::builder(), Result::<(), _>::Err(e), pretty),
+            Ok(x) => Ok(x),
+        }
+    } else if req.uri().path() == "/api/search" {
+        match handle_search(pool, req, &context).await {
+            Ok((search_id, results)) => api_result_to_response(
+                Response::builder()
+                    .header("x-search-id", search_id)
+                    .header("content-type", results.content_type()),
+                Result::<_, Infallible>::Ok(results),
+                pretty,
+            ),
+            Err(e) => api_result_to_response(Response::builder(), Result::<(), _>::Err(e), pretty),
+        }
+    } else if req.uri().path() == "/api/reviews" {
+        match handle_reviews(pool, req, &context).await {
+            Err(e) => {
+                api_result_to_response(Response::builder(), Result::<String, _>::Err(e), pretty)
+            }
+            Ok(x) => Ok(x),
+        }
+    } else if req.uri().path() == "/api/reviews/ws" {
+        match handle_reviews_ws(pool, req, context) {
+            Err(e) => api_result_to_response(Response::builder(), Result::<(), _>::Err(e), pretty),
+            Ok(x) => Ok(x),
+        }
+    } else if req.uri().path() == "/api/reviews/merged" {
+        match handle_merged_reviews(pool, req, &context).await {
+            Err(e) => {
+                api_result_to_response(Response::builder(), Result::<String, _>::Err(e), pretty)
+            }
+            Ok(x) => Ok(x),
+        }
+    } else if req.uri().path() == "/api/reviews/summary" {
+        let envelope = wants_envelope(&req);
+        let result = handle_review_summary(pool, req, &context).await;
+        envelope_to_response(Response::builder(), result, envelope, pretty)
+    } else if req.uri().path() == "/api/reviews/by-rating" {
+        let result = handle_reviews_by_rating(pool, req, &context).await;
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/reviews/distribution" {
+        let envelope = wants_envelope(&req);
+        let result = handle_review_distribution(pool, req, &context).await;
+        envelope_to_response(Response::builder(), result, envelope, pretty)
+    } else if req.uri().path() == "/api/cancel" {
+        let result = handle_cancel(&req, &context);
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/admin/recycle" {
+        let result = handle_admin_recycle(pool, req, &context).await;
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/photos" {
+        let result = handle_photos(pool, req, &context).await;
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/rating" {
+        let result = handle_rating(pool, req, &context).await;
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/location" {
+        match handle_location(&locator, &req, &client_ip, &context) {
+            Ok(result) => {
+                let is_default = result.source == Some(LocationSource::Default);
+                let mut builder =
+                    Response::builder().header("x-location-default", is_default.to_string());
+                if let Some(source) = result.source {
+                    builder = builder.header("x-location-source", source.name());
+                }
+                let envelope = Envelope {
+                    warnings: result.warnings(),
+                    data: result.body(),
+                };
+                envelope_to_response(
+                    builder,
+                    Result::<_, Infallible>::Ok(envelope),
+                    wants_envelope(&req),
+                    pretty,
+                )
+            }
+            Err(e) => api_result_to_response(Response::builder(), Result::<(), _>::Err(e), pretty),
+        }
+    } else if req.uri().path() == "/api/locations" {
+        let result = handle_locations(&locator, req).await;
+        api_result_to_response(Response::builder(), result, pretty)
+    } else if req.uri().path() == "/api/pool" {
+        api_result_to_response(
+            Response::builder(),
+            Result::<_, Infallible>::Ok(PoolStatus::new(&pool, &context)),
+            pretty,
+        )
+    } else if req.uri().path() == "/metrics" {
+        static_response(
+            Response::builder().header("content-type", "text/plain; version=0.0.4"),
+            &[
+                PoolStatus::new(&pool, &context).prometheus_metrics(),
+                context.review_cache.prometheus_metrics(),
+                context.completeness.prometheus_metrics(),
+                context.connections.prometheus_metrics(),
+                context
+                    .page_cache
+                    .as_ref()
+                    .map(|x| x.prometheus_metrics())
+                    .unwrap_or_default(),
+            ]
+            .concat(),
+        )
+    } else if req.uri().path() == "/api/health" {
+        let status = HealthStatus::new(&pool, &context);
+        api_result_to_response(
+            Response::builder().status(status.status()),
+            Result::<_, Infallible>::Ok(status),
+            pretty,
+        )
+    } else if req.uri().path() == "/api/version" {
+        api_result_to_response(
+            Response::builder(),
+            Result::<&VersionInfo, Infallible>::Ok(&version_info),
+            pretty,
+        )
+    } else {
+        for (page, content_type, content) in static_routes() {
+            if req.uri().path() == page {
+                return Ok(static_response(
+                    Response::builder().header("content-type", content_type),
+                    content,
+                )?);
+            }
+        }
+        Ok(static_response(
+            Response::builder().status(404),
+            include_str!("assets/404.html"),
+        )?)
+    }
+}
+
+// Combine built-in detection strings with extra ones from the command line.
+fn with_defaults(defaults: &[&str], extra: &[String]) -> Vec<String> {
+    defaults
+        .iter()
+        .map(|x| x.to_string())
+        .chain(extra.iter().cloned())
+        .collect()
+}
+
+// Parse a "latitude,longitude" pair.
+fn parse_location(s: &str) -> Result<(f64, f64), Box<dyn Error + Send + Sync>> {
+    let (lat, lon) = s
+        .split_once(',')
+        .ok_or_else(|| format!("invalid location {:?}: expected latitude,longitude", s))?;
+    let (lat, lon): (f64, f64) = (lat.trim().parse()?, lon.trim().parse()?);
+    if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+        return Err(format!("location out of range: {}", s).into());
+    }
+    Ok((lat, lon))
+}
+
+async fn bind_listener(host: &str, backlog: u32) -> io::Result<TcpListener> {
+    let addr = lookup_host(host).await?.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("could not resolve host: {}", host),
+        )
+    })?;
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+fn tcp_keepalive_config(args: &Args) -> Result<Option<TcpKeepalive>, Box<dyn Error + Send + Sync>> {
+    if let Some(time) = args.tcp_keepalive {
+        let mut keepalive = TcpKeepalive::new().with_time(Duration::try_from_secs_f64(time)?);
+        if let Some(interval) = args.tcp_keepalive_interval {
+            keepalive = keepalive.with_interval(Duration::try_from_secs_f64(interval)?);
+        }
+        Ok(Some(keepalive))
+    } else if args.tcp_keepalive_interval.is_some() {
+        Err("--tcp-keepalive-interval requires --tcp-keepalive".into())
+    } else {
+        Ok(None)
+    }
+}
+
+// Limit how long and how much a client may take to send its headers, so that
+// slow or malformed clients can't hold connections open indefinitely.
+fn http1_builder_config(args: &Args) -> Result<http1::Builder, Box<dyn Error + Send + Sync>> {
+    // Hyper panics on buffers smaller than this.
+    const MIN_BUF_SIZE: usize = 8192;
+    if args.max_request_buf_size < MIN_BUF_SIZE {
+        return Err(format!("--max-request-buf-size must be at least {}", MIN_BUF_SIZE).into());
+    }
+    let mut builder = http1::Builder::new();
+    builder
+        .timer(TokioTimer::new())
+        .max_buf_size(args.max_request_buf_size)
+        .keep_alive(!args.disable_http_keep_alive);
+    if args.header_read_timeout > 0.0 {
+        builder.header_read_timeout(Duration::try_from_secs_f64(args.header_read_timeout)?);
+    } else {
+        builder.header_read_timeout(None);
+    }
+    Ok(builder)
+}
+
+async fn check_driver(driver: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let status_url = format!("{}/status", driver.trim_end_matches('/'));
+    match reqwest::get(&status_url).await {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) => Err(format!(
+            "WebDriver at {} returned status {}; is chromedriver running at this address?",
+            driver,
+            resp.status()
+        )
+        .into()),
+        Err(e) => Err(format!(
+            "failed to connect to WebDriver at {} ({}); is chromedriver running at this \
+             address? Start it or pass the correct --driver URL.",
+            driver, e
+        )
+        .into()),
+    }
+}
+
 fn static_response(
     builder: Builder,
     data: &str,
 ) -> Result<Response<BoxBody<Bytes, Infallible>>, http::Error> {
     builder.body(BoxBody::new(Full::<Bytes>::from(data.to_owned())))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn asset_files(dir: &Path, files: &mut Vec<PathBuf>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                asset_files(&path, files);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn static_pages_have_content_types() {
+        for (path, _) in PAGE_MAPPING {
+            assert!(
+                static_content_type(path).is_some(),
+                "no content type for {:?}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn every_asset_is_served() {
+        let base = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/assets");
+        let mut files = Vec::new();
+        asset_files(&base, &mut files);
+        assert!(!files.is_empty());
+        let routes: Vec<_> = static_routes().collect();
+        for file in files {
+            let relative = file.strip_prefix(&base).unwrap().to_str().unwrap();
+            // The index page is served at the root rather than by name.
+            let path = match relative {
+                "index.html" => "/".to_owned(),
+                x => format!("/{}", x),
+            };
+            let Some((_, content_type, content)) = routes.iter().find(|x| x.0 == path) else {
+                panic!("{} is not served", relative);
+            };
+            assert_eq!(Some(*content_type), static_content_type(&path));
+            assert_eq!(*content, std::fs::read_to_string(&file).unwrap());
+        }
+    }
+}
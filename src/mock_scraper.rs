@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+
+use super::client::{
+    GeoLocation, PlaceRating, Review, ReviewIter, ReviewListOptions, ScrapeError, Scraper,
+    SearchResult,
+};
+
+// A scraper which returns canned results, for testing everything downstream
+// of scraping without a browser. Clones share their call log, so a test can
+// keep one while the pool owns the others.
+//
+// Like a browser, each scraper keeps the location it last emulated between
+// requests. Operations follow the Scraper contract by setting or clearing it
+// first, and each logged call records the location it ran with.
+#[derive(Clone)]
+pub struct MockScraper {
+    pub search_result: SearchResult,
+    pub reviews: Vec<Review>,
+    pub total_count: Option<u64>,
+    // Fail every review listing, as if the place's page didn't load.
+    pub fail_reviews: bool,
+    pub calls: Arc<Mutex<Vec<String>>>,
+    location: Option<(f64, f64)>,
+}
+
+impl MockScraper {
+    pub fn new(search_result: SearchResult, reviews: Vec<Review>) -> Self {
+        MockScraper {
+            search_result,
+            reviews,
+            total_count: None,
+            fail_reviews: false,
+            calls: Default::default(),
+            location: None,
+        }
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn log(&self, call: String) {
+        let location = match self.location {
+            Some((lat, lon)) => format!("{},{}", lat, lon),
+            None => "none".to_owned(),
+        };
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("{} @{}", call, location));
+    }
+
+    fn emulate(&mut self, location: Option<&GeoLocation>) {
+        self.location = location.map(|x| (x.latitude, x.longitude));
+    }
+}
+
+pub fn review(id: &str, timestamp: f64, rating: f64, content: &str) -> Review {
+    Review {
+        review_id: id.to_owned(),
+        timestamp,
+        author: format!("author of {}", id),
+        content: content.to_owned(),
+        rating,
+        is_local_guide: false,
+        author_review_count: None,
+        raw: None,
+        place: None,
+    }
+}
+
+impl Scraper for MockScraper {
+    async fn search(
+        &mut self,
+        search: &str,
+        location: &GeoLocation,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.emulate(Some(location));
+        self.log(format!("search {}", search));
+        Ok(self.search_result.clone())
+    }
+
+    async fn list_reviews(
+        &mut self,
+        url: &str,
+        location: &GeoLocation,
+        _options: &ReviewListOptions,
+    ) -> Result<ReviewIter, ScrapeError> {
+        self.emulate(Some(location));
+        self.log(format!("list_reviews {}", url));
+        if self.fail_reviews {
+            return Err(ScrapeError::parse_error("no reviews were found"));
+        }
+        Ok(ReviewIter::from_reviews(
+            self.reviews.clone(),
+            self.total_count,
+        ))
+    }
+
+    async fn list_photos(
+        &mut self,
+        url: &str,
+        _max_count: usize,
+    ) -> Result<Vec<String>, ScrapeError> {
+        self.emulate(None);
+        self.log(format!("list_photos {}", url));
+        Ok(vec![])
+    }
+
+    async fn place_rating(&mut self, url: &str) -> Result<PlaceRating, ScrapeError> {
+        self.emulate(None);
+        self.log(format!("place_rating {}", url));
+        Ok(PlaceRating::default())
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ScrapeError> {
+        self.log("reconnect".to_owned());
+        Ok(())
+    }
+
+    async fn close(self) -> Result<(), ScrapeError> {
+        Ok(())
+    }
+}
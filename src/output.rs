@@ -0,0 +1,387 @@
+use std::str::FromStr;
+
+use bytes::Bytes;
+use serde_json::json;
+
+use super::calendar::format_rfc3339;
+use super::client::Review;
+use super::sentiment::sentiment_score;
+
+// Encodes a stream of review pages into a response body. An encoder is fed
+// an optional total, any number of pages, and then exactly one error or end
+// marker.
+pub trait OutputEncoder: Send {
+    fn content_type(&self) -> &'static str;
+
+    // Report how many reviews the place has in total, before any pages.
+    fn encode_total(&mut self, total: u64) -> Bytes;
+
+    fn encode_page(&mut self, reviews: &[Review]) -> Bytes;
+
+    fn encode_error(&mut self, error: &str) -> Bytes;
+
+    // Report a problem which didn't stop the scrape, just before the end.
+    fn encode_warning(&mut self, warning: &str) -> Bytes;
+
+    // Report how many reviews and pages have been scraped so far, before
+    // any filtering.
+    fn encode_progress(&mut self, fetched: usize, pages: usize) -> Bytes;
+
+    // Finish the output. If truncated is true, there were more reviews which
+    // were left out.
+    fn encode_end(&mut self, truncated: bool) -> Bytes;
+}
+
+pub enum ReviewFormat {
+    NdJson,
+    Json,
+    JsonArray,
+}
+
+impl ReviewFormat {
+    pub fn encoder(&self, encoding: ReviewEncoding, place: &str) -> Box<dyn OutputEncoder> {
+        match self {
+            ReviewFormat::NdJson => Box::new(NdJsonEncoder { encoding }),
+            ReviewFormat::Json => Box::new(JsonEncoder {
+                encoding,
+                place: place.to_owned(),
+                started: false,
+                wrote_review: false,
+                warnings: Vec::new(),
+            }),
+            ReviewFormat::JsonArray => Box::new(JsonArrayEncoder {
+                encoding,
+                started: false,
+                wrote_item: false,
+            }),
+        }
+    }
+}
+
+impl FromStr for ReviewFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(ReviewFormat::NdJson),
+            "json" => Ok(ReviewFormat::Json),
+            "json-array" => Ok(ReviewFormat::JsonArray),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+// The order to emit reviews in. Reviews are scraped newest-first, so emitting
+// them oldest-first means buffering the whole scrape.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ReviewOrder {
+    Newest,
+    Oldest,
+}
+
+impl FromStr for ReviewOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(ReviewOrder::Newest),
+            "oldest" => Ok(ReviewOrder::Oldest),
+            _ => Err(format!("unknown order: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum TimeFormat {
+    Epoch,
+    Iso8601,
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "epoch" => Ok(TimeFormat::Epoch),
+            "iso8601" => Ok(TimeFormat::Iso8601),
+            _ => Err(format!("unknown time format: {}", s)),
+        }
+    }
+}
+
+// Every field that an encoded review can have.
+const REVIEW_FIELDS: &[&str] = &[
+    "review_id",
+    "timestamp",
+    "author",
+    "content",
+    "rating",
+    "is_local_guide",
+    "author_review_count",
+    "raw",
+    "place",
+    "sentiment",
+];
+
+// A comma-separated list of the review fields to output, e.g.
+// "timestamp,rating".
+pub struct ReviewFields(Vec<String>);
+
+impl FromStr for ReviewFields {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            if !REVIEW_FIELDS.contains(&name) {
+                return Err(format!("unknown review field: {}", name));
+            }
+            fields.push(name.to_owned());
+        }
+        if fields.is_empty() {
+            Err("no review fields were listed".to_owned())
+        } else {
+            Ok(ReviewFields(fields))
+        }
+    }
+}
+
+// Options for how each individual review is encoded.
+pub struct ReviewEncoding {
+    pub time_format: TimeFormat,
+    pub sentiment: bool,
+
+    // Only include these fields, rather than all of them.
+    pub fields: Option<ReviewFields>,
+}
+
+impl ReviewEncoding {
+    pub fn encode(&self, reviews: &[Review]) -> String {
+        if matches!(self.time_format, TimeFormat::Epoch) && !self.sentiment && self.fields.is_none()
+        {
+            serde_json::to_string(reviews).unwrap()
+        } else {
+            serde_json::to_string(
+                &reviews
+                    .iter()
+                    .map(|x| self.review_value(x))
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()
+        }
+    }
+
+    pub fn review_value(&self, review: &Review) -> serde_json::Value {
+        let mut value = serde_json::to_value(review).unwrap();
+        if let TimeFormat::Iso8601 = self.time_format {
+            value["timestamp"] = format_rfc3339(review.timestamp).into();
+        }
+        if self.sentiment {
+            value["sentiment"] = sentiment_score(&review.content).into();
+        }
+        if let (Some(ReviewFields(fields)), Some(object)) = (&self.fields, value.as_object_mut()) {
+            object.retain(|k, _| fields.contains(k));
+        }
+        value
+    }
+}
+
+// One JSON array of reviews per line, with errors as {"error": ...} lines,
+// warnings as {"warning": ...} lines, and the total as a {"total_reviews": ...}
+// line.
+struct NdJsonEncoder {
+    encoding: ReviewEncoding,
+}
+
+impl OutputEncoder for NdJsonEncoder {
+    fn content_type(&self) -> &'static str {
+        "application/x-ndjson"
+    }
+
+    fn encode_total(&mut self, total: u64) -> Bytes {
+        Bytes::from(serde_json::to_string(&json!({"total_reviews": total})).unwrap() + "\n")
+    }
+
+    fn encode_page(&mut self, reviews: &[Review]) -> Bytes {
+        Bytes::from(self.encoding.encode(reviews) + "\n")
+    }
+
+    fn encode_error(&mut self, error: &str) -> Bytes {
+        Bytes::from(serde_json::to_string(&json!({"error": error})).unwrap() + "\n")
+    }
+
+    fn encode_warning(&mut self, warning: &str) -> Bytes {
+        Bytes::from(serde_json::to_string(&json!({"warning": warning})).unwrap() + "\n")
+    }
+
+    fn encode_progress(&mut self, fetched: usize, pages: usize) -> Bytes {
+        let progress = json!({"progress": {"fetched": fetched, "pages": pages}});
+        Bytes::from(serde_json::to_string(&progress).unwrap() + "\n")
+    }
+
+    fn encode_end(&mut self, _truncated: bool) -> Bytes {
+        Bytes::new()
+    }
+}
+
+// A single {"place", "reviews", "truncated"} object, which additionally has
+// a "total_reviews" field if the total was known, an "error" field if
+// scraping failed part way through, and a "warnings" list if there were any.
+struct JsonEncoder {
+    encoding: ReviewEncoding,
+    place: String,
+    started: bool,
+    wrote_review: bool,
+    warnings: Vec<String>,
+}
+
+impl JsonEncoder {
+    fn header(&mut self) -> String {
+        self.header_with_total(None)
+    }
+
+    fn header_with_total(&mut self, total: Option<u64>) -> String {
+        if self.started {
+            String::new()
+        } else {
+            self.started = true;
+            let total = match total {
+                Some(x) => format!("\"total_reviews\":{},", x),
+                None => String::new(),
+            };
+            format!(
+                "{{\"place\":{},{}\"reviews\":[",
+                serde_json::to_string(&self.place).unwrap(),
+                total
+            )
+        }
+    }
+
+    // The fields which follow the reviews, along with the closing brace.
+    fn footer(&self, truncated: bool, error: Option<&str>) -> String {
+        let mut result = format!("],\"truncated\":{}", truncated);
+        if let Some(error) = error {
+            result.push_str(&format!(
+                ",\"error\":{}",
+                serde_json::to_string(error).unwrap()
+            ));
+        }
+        if !self.warnings.is_empty() {
+            result.push_str(&format!(
+                ",\"warnings\":{}",
+                serde_json::to_string(&self.warnings).unwrap()
+            ));
+        }
+        result + "}"
+    }
+}
+
+impl OutputEncoder for JsonEncoder {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode_total(&mut self, total: u64) -> Bytes {
+        Bytes::from(self.header_with_total(Some(total)))
+    }
+
+    fn encode_page(&mut self, reviews: &[Review]) -> Bytes {
+        let mut result = self.header();
+        for review in reviews {
+            if self.wrote_review {
+                result.push(',');
+            }
+            self.wrote_review = true;
+            result.push_str(&self.encoding.review_value(review).to_string());
+        }
+        Bytes::from(result)
+    }
+
+    fn encode_error(&mut self, error: &str) -> Bytes {
+        Bytes::from(self.header() + self.footer(false, Some(error)).as_str())
+    }
+
+    fn encode_warning(&mut self, warning: &str) -> Bytes {
+        self.warnings.push(warning.to_owned());
+        Bytes::new()
+    }
+
+    fn encode_progress(&mut self, _fetched: usize, _pages: usize) -> Bytes {
+        Bytes::new()
+    }
+
+    fn encode_end(&mut self, truncated: bool) -> Bytes {
+        Bytes::from(self.header() + self.footer(truncated, None).as_str())
+    }
+}
+
+// A single JSON array of review objects, streamed element by element. If
+// scraping fails, the last element is an {"error": ...} object, so the output
+// is always valid JSON. Warnings are {"warning": ...} elements in the same
+// way. The total is not reported in this format.
+struct JsonArrayEncoder {
+    encoding: ReviewEncoding,
+    started: bool,
+    wrote_item: bool,
+}
+
+impl JsonArrayEncoder {
+    // Start the array if needed, and separate the next item from the last.
+    fn item_prefix(&mut self) -> &'static str {
+        let prefix = match (self.started, self.wrote_item) {
+            (false, _) => "[",
+            (true, false) => "",
+            (true, true) => ",",
+        };
+        self.started = true;
+        self.wrote_item = true;
+        prefix
+    }
+}
+
+impl OutputEncoder for JsonArrayEncoder {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn encode_total(&mut self, _total: u64) -> Bytes {
+        Bytes::new()
+    }
+
+    fn encode_page(&mut self, reviews: &[Review]) -> Bytes {
+        let mut result = String::new();
+        for review in reviews {
+            result.push_str(self.item_prefix());
+            result.push_str(&self.encoding.review_value(review).to_string());
+        }
+        Bytes::from(result)
+    }
+
+    fn encode_error(&mut self, error: &str) -> Bytes {
+        Bytes::from(format!(
+            "{}{}]",
+            self.item_prefix(),
+            serde_json::to_string(&json!({"error": error})).unwrap()
+        ))
+    }
+
+    fn encode_warning(&mut self, warning: &str) -> Bytes {
+        Bytes::from(format!(
+            "{}{}",
+            self.item_prefix(),
+            serde_json::to_string(&json!({"warning": warning})).unwrap()
+        ))
+    }
+
+    fn encode_progress(&mut self, _fetched: usize, _pages: usize) -> Bytes {
+        Bytes::new()
+    }
+
+    fn encode_end(&mut self, _truncated: bool) -> Bytes {
+        if self.started {
+            Bytes::from("]")
+        } else {
+            Bytes::from("[]")
+        }
+    }
+}
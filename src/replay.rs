@@ -0,0 +1,383 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use thirtyfour::prelude::{WebDriverError, WebDriverResult};
+use tokio::sync::mpsc::Sender;
+use url::Url;
+
+use super::client::{
+    scrape_photos, scrape_place_rating, scrape_reviews, scrape_search_result, ClientOptions,
+    GeoLocation, LocationInfo, PageDriver, PageSource, PlaceRating, ReviewIter, ReviewListOptions,
+    ScrapeError, Scraper, Script, SearchResult,
+};
+
+// One result read from a loaded page or fetched from Google, stored one per
+// line in a recording. Page results are keyed by the operation and target
+// (e.g. "reviews <url>") they were read for. Errors are only kept as their
+// messages.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Interaction {
+    Script {
+        page: String,
+        name: String,
+        args: Vec<serde_json::Value>,
+        result: Result<serde_json::Value, String>,
+    },
+    CurrentUrl {
+        page: String,
+        result: Result<String, String>,
+    },
+    FindAttr {
+        page: String,
+        xpath: String,
+        attr: String,
+        result: Result<Option<Option<String>>, String>,
+    },
+    Response {
+        url: String,
+        result: Result<String, String>,
+    },
+}
+
+impl Interaction {
+    fn key(&self) -> String {
+        match self {
+            Interaction::Script {
+                page, name, args, ..
+            } => script_key(page, name, args),
+            Interaction::CurrentUrl { page, .. } => current_url_key(page),
+            Interaction::FindAttr {
+                page, xpath, attr, ..
+            } => find_attr_key(page, xpath, attr),
+            Interaction::Response { url, .. } => response_key(url),
+        }
+    }
+}
+
+fn script_key(page: &str, name: &str, args: &[serde_json::Value]) -> String {
+    format!(
+        "script\n{}\n{}\n{}",
+        page,
+        name,
+        serde_json::Value::from(args)
+    )
+}
+
+fn current_url_key(page: &str) -> String {
+    format!("current_url\n{}", page)
+}
+
+fn find_attr_key(page: &str, xpath: &str, attr: &str) -> String {
+    format!("find_attr\n{}\n{}\n{}", page, xpath, attr)
+}
+
+fn response_key(url: &str) -> String {
+    format!("response\n{}", url)
+}
+
+pub fn to_recorded<T: Clone, E: std::fmt::Display>(result: &Result<T, E>) -> Result<T, String> {
+    result.as_ref().cloned().map_err(|e| format!("{}", e))
+}
+
+// Appends interactions to a recording as a client scrapes, which
+// ReplayScraper can play back later. Review pages are only recorded as they
+// are fetched, so a recording covers just as much as the original requests
+// asked for.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, interaction: &Interaction) {
+        let line = serde_json::to_string(interaction).expect("serialize interaction") + "\n";
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            println!("error writing recording: {}", e);
+        }
+    }
+}
+
+// The interactions of a recording, grouped by what they were read from. When
+// the same thing is read more than once, each recorded result is returned in
+// turn, and then the last one forever.
+pub struct Replay {
+    interactions: Mutex<HashMap<String, VecDeque<Interaction>>>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Replay::parse(
+            BufReader::new(File::open(path)?),
+            &path.display().to_string(),
+        )
+    }
+
+    fn parse<R: BufRead>(reader: R, name: &str) -> io::Result<Self> {
+        let mut interactions: HashMap<String, VecDeque<Interaction>> = HashMap::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let interaction: Interaction = serde_json::from_str(&line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: {}", name, i + 1, e),
+                )
+            })?;
+            interactions
+                .entry(interaction.key())
+                .or_default()
+                .push_back(interaction);
+        }
+        Ok(Replay {
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    fn next(&self, key: String) -> Result<Interaction, String> {
+        let mut interactions = self.interactions.lock().unwrap();
+        let queue = interactions
+            .get_mut(&key)
+            .ok_or_else(|| format!("nothing was recorded for {:?}", key))?;
+        if queue.len() > 1 {
+            Ok(queue.pop_front().unwrap())
+        } else {
+            Ok(queue[0].clone())
+        }
+    }
+
+    pub fn response(&self, url: &str) -> Result<String, ScrapeError> {
+        let Interaction::Response { result, .. } = self
+            .next(response_key(url))
+            .map_err(ScrapeError::fatal_parse_error)?
+        else {
+            unreachable!("recording keys include the kind");
+        };
+        result.map_err(ScrapeError::parse_error)
+    }
+}
+
+// Recorded page errors are played back as driver errors with the original
+// message.
+fn replayed_error(message: String) -> WebDriverError {
+    WebDriverError::ParseError(message)
+}
+
+// A page which was loaded while recording, as ReplayScraper sees it.
+pub struct ReplayPage {
+    replay: Arc<Replay>,
+    page: String,
+}
+
+impl ReplayPage {
+    fn new(replay: &Arc<Replay>, operation: &str, target: &str) -> Self {
+        ReplayPage {
+            replay: replay.clone(),
+            page: format!("{} {}", operation, target),
+        }
+    }
+}
+
+impl PageDriver for ReplayPage {
+    async fn execute(
+        &self,
+        script: Script,
+        args: Vec<serde_json::Value>,
+    ) -> WebDriverResult<serde_json::Value> {
+        let key = script_key(&self.page, script.name, &args);
+        let Interaction::Script { result, .. } = self.replay.next(key).map_err(replayed_error)?
+        else {
+            unreachable!("recording keys include the kind");
+        };
+        result.map_err(replayed_error)
+    }
+
+    async fn current_url(&self) -> WebDriverResult<Url> {
+        let key = current_url_key(&self.page);
+        let Interaction::CurrentUrl { result, .. } =
+            self.replay.next(key).map_err(replayed_error)?
+        else {
+            unreachable!("recording keys include the kind");
+        };
+        Url::parse(&result.map_err(replayed_error)?)
+            .map_err(|e| replayed_error(format!("recorded URL is invalid: {}", e)))
+    }
+
+    async fn find_attr(
+        &self,
+        xpath: &'static str,
+        attr: &'static str,
+    ) -> WebDriverResult<Option<Option<String>>> {
+        let key = find_attr_key(&self.page, xpath, attr);
+        let Interaction::FindAttr { result, .. } = self.replay.next(key).map_err(replayed_error)?
+        else {
+            unreachable!("recording keys include the kind");
+        };
+        result.map_err(replayed_error)
+    }
+
+    fn page_source(&self) -> PageSource {
+        PageSource::Replay(self.replay.clone())
+    }
+}
+
+// Plays back a recording made by a client with a Recorder, so that the server
+// can run without a browser or network access. The recorded page results go
+// through the same scraping and parsing code as they did live, so the options
+// should match the ones used while recording. Place details for search
+// results aren't recorded, so they're only filled in from the results list.
+#[derive(Clone)]
+pub struct ReplayScraper {
+    replay: Arc<Replay>,
+    options: ClientOptions,
+}
+
+impl ReplayScraper {
+    pub fn load(path: &Path, options: &ClientOptions) -> io::Result<Self> {
+        Ok(ReplayScraper {
+            replay: Arc::new(Replay::load(path)?),
+            options: options.clone(),
+        })
+    }
+
+    async fn search_filled(
+        &self,
+        search: &str,
+        found: Option<&Sender<LocationInfo>>,
+    ) -> Result<SearchResult, ScrapeError> {
+        let page = ReplayPage::new(&self.replay, "search", search);
+        scrape_search_result(&page, &self.options, 0, found).await
+    }
+}
+
+impl Scraper for ReplayScraper {
+    async fn search(
+        &mut self,
+        search: &str,
+        _location: &GeoLocation,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.search_filled(search, None).await
+    }
+
+    async fn search_incremental(
+        &mut self,
+        search: &str,
+        _location: &GeoLocation,
+        found: Sender<LocationInfo>,
+    ) -> Result<SearchResult, ScrapeError> {
+        self.search_filled(search, Some(&found)).await
+    }
+
+    async fn list_reviews(
+        &mut self,
+        url: &str,
+        _location: &GeoLocation,
+        options: &ReviewListOptions,
+    ) -> Result<ReviewIter, ScrapeError> {
+        let page = ReplayPage::new(&self.replay, "reviews", url);
+        scrape_reviews(&page, &self.options, options).await
+    }
+
+    async fn list_photos(
+        &mut self,
+        url: &str,
+        max_count: usize,
+    ) -> Result<Vec<String>, ScrapeError> {
+        scrape_photos(&ReplayPage::new(&self.replay, "photos", url), max_count).await
+    }
+
+    async fn place_rating(&mut self, url: &str) -> Result<PlaceRating, ScrapeError> {
+        scrape_place_rating(&ReplayPage::new(&self.replay, "rating", url)).await
+    }
+
+    async fn reconnect(&mut self) -> Result<(), ScrapeError> {
+        Ok(())
+    }
+
+    async fn close(self) -> Result<(), ScrapeError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::test_client_options;
+
+    const PLACE_URL: &str = "https://www.google.com/maps/place/Corner+Cafe/data=!4m2!3m1!1s0x89c259a61c75684f:0x79d31adb123348d2";
+
+    fn fixture_scraper() -> ReplayScraper {
+        let fixture = include_str!("testdata/replay_reviews.jsonl");
+        ReplayScraper {
+            replay: Arc::new(Replay::parse(fixture.as_bytes(), "fixture").unwrap()),
+            options: test_client_options(),
+        }
+    }
+
+    fn location() -> GeoLocation {
+        GeoLocation {
+            latitude: 40.7,
+            longitude: -74.0,
+            accuracy: 100.0,
+            altitude: None,
+            altitude_accuracy: None,
+        }
+    }
+
+    // The fixture's first click finds no button, so the retry sleeps.
+    #[tokio::test(start_paused = true)]
+    async fn replays_recorded_review_pages() {
+        let mut scraper = fixture_scraper();
+        let mut it = scraper
+            .list_reviews(PLACE_URL, &location(), &Default::default())
+            .await
+            .unwrap();
+        assert_eq!(it.total_count(), Some(3));
+
+        let first = it.next().await.unwrap().unwrap();
+        let summary: Vec<_> = first
+            .iter()
+            .map(|x| (x.review_id.as_str(), x.author.as_str(), x.rating))
+            .collect();
+        assert_eq!(summary, vec![("r1", "Ana", 5.0), ("r2", "Ben", 4.0)]);
+        assert_eq!(first[0].content, "Great coffee.");
+        assert_eq!(first[0].timestamp, 1700000000.0);
+        assert!(first[0].is_local_guide);
+        assert_eq!(first[0].author_review_count, Some(52));
+        assert!(!first[1].is_local_guide);
+        assert_eq!(first[1].author_review_count, Some(3));
+
+        // The second page comes from the recorded response to the URL built
+        // from the first page's token.
+        let second = it.next().await.unwrap().unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].review_id, "r3");
+        assert_eq!(second[0].rating, 2.0);
+        assert!(it.next().await.unwrap().is_none());
+        assert_eq!(it.skipped_reviews(), 0);
+    }
+
+    #[tokio::test]
+    async fn unrecorded_page_fails() {
+        let mut scraper = fixture_scraper();
+        let url = "https://www.google.com/maps/place/Elsewhere";
+        let result = scraper
+            .list_reviews(url, &location(), &Default::default())
+            .await;
+        assert!(matches!(result, Err(ScrapeError::WebDriverError(_))));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::client::Review;
+
+// Previously scraped reviews for each place, so that repeated requests can be
+// served without a browser, or refreshed by only scraping newer reviews.
+pub struct ReviewCache {
+    inner: Mutex<TimedLru<Arc<CachedReviews>>>,
+
+    // Held while refreshing a key, so that concurrent requests for the same
+    // place wait for one scrape rather than each starting their own.
+    refresh_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+
+    // Counts of each CacheStatus, in declaration order.
+    status_counts: [AtomicU64; 3],
+}
+
+// Where a cached response came from.
+#[derive(Clone, Copy)]
+pub enum CacheStatus {
+    // The cached reviews were fresh enough.
+    Hit,
+    // The reviews had to be (at least partially) scraped.
+    Miss,
+    // Another request refreshed the reviews while this one waited.
+    Coalesced,
+}
+
+impl Display for CacheStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheStatus::Hit => write!(f, "HIT"),
+            CacheStatus::Miss => write!(f, "MISS"),
+            CacheStatus::Coalesced => write!(f, "COALESCED"),
+        }
+    }
+}
+
+// A bounded map which evicts the least recently used entry when full, and
+// remembers when each entry was inserted.
+struct TimedLru<V> {
+    max_entries: usize,
+    next_use: u64,
+    entries: HashMap<String, TimedEntry<V>>,
+}
+
+struct TimedEntry<V> {
+    inserted_at: Instant,
+    last_use: u64,
+    value: V,
+}
+
+impl<V: Clone> TimedLru<V> {
+    fn new(max_entries: usize) -> Self {
+        TimedLru {
+            max_entries,
+            next_use: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    // Get an entry along with its age.
+    fn get(&mut self, key: &str) -> Option<(Duration, V)> {
+        let next_use = self.next_use;
+        self.next_use += 1;
+        let entry = self.entries.get_mut(key)?;
+        entry.last_use = next_use;
+        Some((entry.inserted_at.elapsed(), entry.value.clone()))
+    }
+
+    fn insert(&mut self, key: String, value: V) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) {
+            while self.entries.len() >= self.max_entries {
+                let least_recent = self
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, x)| x.last_use)
+                    .map(|(k, _)| k.clone())
+                    .unwrap();
+                self.entries.remove(&least_recent);
+            }
+        }
+        let last_use = self.next_use;
+        self.next_use += 1;
+        self.entries.insert(
+            key,
+            TimedEntry {
+                inserted_at: Instant::now(),
+                last_use,
+                value,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+}
+
+// One request's reference to the refresh lock for a key. The lock is forgotten
+// once every request using it has dropped its reference, even if a request was
+// cancelled while it waited.
+pub struct RefreshLock<'a> {
+    cache: &'a ReviewCache,
+    key: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl RefreshLock<'_> {
+    pub async fn lock(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+impl Drop for RefreshLock<'_> {
+    fn drop(&mut self) {
+        let mut locks = self.cache.refresh_locks.lock().unwrap();
+        // One reference is ours, and the other is the map's.
+        if Arc::strong_count(&self.lock) <= 2 {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+pub struct CachedReviews {
+    // Newest first.
+    pub reviews: Vec<Review>,
+
+    // If true, the scrape stopped before reaching the oldest review.
+    pub truncated: bool,
+}
+
+impl CachedReviews {
+    pub fn newest_timestamp(&self) -> Option<f64> {
+        self.reviews.iter().map(|x| x.timestamp).reduce(f64::max)
+    }
+}
+
+impl ReviewCache {
+    pub fn new(max_entries: usize) -> Self {
+        ReviewCache {
+            inner: Mutex::new(TimedLru::new(max_entries)),
+            refresh_locks: Mutex::new(HashMap::new()),
+            status_counts: Default::default(),
+        }
+    }
+
+    pub fn record(&self, status: CacheStatus) {
+        self.status_counts[status as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Get the lock which must be held while refreshing the key.
+    pub fn refresh_lock(&self, key: &str) -> RefreshLock<'_> {
+        let lock = self
+            .refresh_locks
+            .lock()
+            .unwrap()
+            .entry(key.to_owned())
+            .or_default()
+            .clone();
+        RefreshLock {
+            cache: self,
+            key: key.to_owned(),
+            lock,
+        }
+    }
+
+    // Render the status counts in the Prometheus text exposition format.
+    pub fn prometheus_metrics(&self) -> String {
+        let name = "review_plot_review_cache_requests_total";
+        let mut result = format!(
+            "# HELP {name} Number of cached review requests by result.\n# TYPE {name} counter\n"
+        );
+        for status in [CacheStatus::Hit, CacheStatus::Miss, CacheStatus::Coalesced] {
+            let count = self.status_counts[status as usize].load(Ordering::Relaxed);
+            result.push_str(&format!(
+                "{name}{{result=\"{}\"}} {count}\n",
+                status.to_string().to_lowercase()
+            ));
+        }
+        result
+    }
+
+    // Get the cached reviews for a key along with their age.
+    pub fn get(&self, key: &str) -> Option<(Duration, Arc<CachedReviews>)> {
+        self.inner.lock().unwrap().get(key)
+    }
+
+    pub fn insert(&self, key: String, reviews: Arc<CachedReviews>) {
+        self.inner.lock().unwrap().insert(key, reviews);
+    }
+}
+
+// Parsed review pages keyed by their pagination URL, so that overlapping
+// scrapes of the same place can skip refetching pages they share.
+pub struct PageCache {
+    ttl: Duration,
+    inner: Mutex<TimedLru<Arc<CachedPage>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+pub struct CachedPage {
+    pub reviews: Vec<Review>,
+    pub next_url: Option<String>,
+    pub skipped: usize,
+}
+
+impl PageCache {
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        PageCache {
+            ttl,
+            inner: Mutex::new(TimedLru::new(max_entries)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    // Get a page if it was fetched within the TTL.
+    pub fn get(&self, url: &str) -> Option<Arc<CachedPage>> {
+        let mut inner = self.inner.lock().unwrap();
+        let result = match inner.get(url) {
+            Some((age, page)) if age <= self.ttl => Some(page),
+            Some(_) => {
+                inner.remove(url);
+                None
+            }
+            None => None,
+        };
+        let counter = if result.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    pub fn insert(&self, url: String, page: Arc<CachedPage>) {
+        self.inner.lock().unwrap().insert(url, page);
+    }
+
+    // Render the hit and miss counts in the Prometheus text exposition format.
+    pub fn prometheus_metrics(&self) -> String {
+        let name = "review_plot_page_cache_requests_total";
+        format!(
+            "# HELP {name} Number of review page lookups by result.\n# TYPE {name} counter\n\
+             {name}{{result=\"hit\"}} {}\n{name}{{result=\"miss\"}} {}\n",
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refresh_locks_are_forgotten_when_dropped() {
+        let cache = ReviewCache::new(10);
+        let first = cache.refresh_lock("a");
+        let guard = first.lock().await;
+
+        // A request which gives up while waiting still lets go of the lock.
+        let waiting = cache.refresh_lock("a");
+        {
+            let wait = waiting.lock();
+            tokio::pin!(wait);
+            assert!(futures::poll!(&mut wait).is_pending());
+        }
+        drop(waiting);
+        assert_eq!(cache.refresh_locks.lock().unwrap().len(), 1);
+
+        drop(guard);
+        drop(first);
+        assert!(cache.refresh_locks.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,66 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    sync::{Arc, Mutex},
+};
+
+use futures::future::{AbortHandle, AbortRegistration};
+
+// Tracks the scrapes in progress by ID, so that they can be cancelled while
+// they run.
+#[derive(Default)]
+pub struct ScrapeRegistry {
+    id_hasher: RandomState,
+    inner: Arc<Mutex<ScrapeRegistryInner>>,
+}
+
+#[derive(Default)]
+struct ScrapeRegistryInner {
+    next_id: u64,
+    handles: HashMap<String, AbortHandle>,
+}
+
+impl ScrapeRegistry {
+    // Register a new scrape. Wrap the scrape's future with the returned
+    // registration using futures::future::Abortable so that cancel() can stop
+    // it. The scrape stays registered until the returned guard is dropped.
+    pub fn register(&self) -> (RegisteredScrape, AbortRegistration) {
+        let (handle, registration) = AbortHandle::new_pair();
+        let mut inner = self.inner.lock().unwrap();
+
+        // Hash the counter so that IDs can't be guessed from one another.
+        let id = format!("{:016x}", self.id_hasher.hash_one(inner.next_id));
+        inner.next_id += 1;
+        inner.handles.insert(id.clone(), handle);
+        (
+            RegisteredScrape {
+                id,
+                registry: self.inner.clone(),
+            },
+            registration,
+        )
+    }
+
+    // Abort the scrape with the given ID, returning false if there is no such
+    // scrape (for example, because it already finished).
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.inner.lock().unwrap().handles.remove(id) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct RegisteredScrape {
+    pub id: String,
+    registry: Arc<Mutex<ScrapeRegistryInner>>,
+}
+
+impl Drop for RegisteredScrape {
+    fn drop(&mut self) {
+        self.registry.lock().unwrap().handles.remove(&self.id);
+    }
+}
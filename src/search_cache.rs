@@ -0,0 +1,66 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// A short-lived cache of search result URLs, so that clients can refer to a
+// result by (search_id, index) rather than sending its full URL back.
+pub struct SearchCache {
+    ttl: Duration,
+    max_entries: usize,
+    id_hasher: RandomState,
+    inner: Mutex<SearchCacheInner>,
+}
+
+struct SearchCacheInner {
+    next_id: u64,
+    entries: HashMap<String, (Instant, Vec<String>)>,
+}
+
+impl SearchCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        SearchCache {
+            ttl,
+            max_entries,
+            id_hasher: RandomState::new(),
+            inner: Mutex::new(SearchCacheInner {
+                next_id: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn insert(&self, urls: Vec<String>) -> String {
+        let mut inner = self.inner.lock().unwrap();
+        let ttl = self.ttl;
+        inner
+            .entries
+            .retain(|_, (created, _)| created.elapsed() < ttl);
+        while inner.entries.len() >= self.max_entries.max(1) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, (created, _))| *created)
+                .map(|(k, _)| k.clone())
+                .unwrap();
+            inner.entries.remove(&oldest);
+        }
+
+        // Hash the counter so that IDs can't be guessed from one another.
+        let id = format!("{:016x}", self.id_hasher.hash_one(inner.next_id));
+        inner.next_id += 1;
+        inner.entries.insert(id.clone(), (Instant::now(), urls));
+        id
+    }
+
+    pub fn resolve(&self, id: &str, index: usize) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        let (created, urls) = inner.entries.get(id)?;
+        if created.elapsed() >= self.ttl {
+            return None;
+        }
+        urls.get(index).cloned()
+    }
+}
@@ -0,0 +1,55 @@
+// A rough, lexicon-based sentiment heuristic for review text.
+//
+// This only looks at individual words (with simple negation handling), so it
+// will misjudge sarcasm, idioms, and most non-English text. It is meant for
+// quick triage, not analysis.
+
+const POSITIVE: &str = "
+    amazing awesome beautiful best clean comfortable convenient delicious enjoy enjoyed
+    excellent fantastic fast favorite fresh friendly fun good great happy helpful incredible
+    kind love loved lovely nice perfect pleasant polite professional quick recommend
+    recommended reasonable tasty welcoming wonderful worth
+";
+
+const NEGATIVE: &str = "
+    awful bad broken cold dirty disappointed disappointing disgusting expensive gross hate
+    hated horrible inedible lazy mediocre mess messy overpriced poor rude scam slow smelly
+    terrible unfriendly unhelpful unprofessional wait waited waste worse worst wrong
+";
+
+const NEGATIONS: &str = "not no never none nothing hardly barely";
+
+// How many words after a negation are flipped.
+const NEGATION_WINDOW: usize = 3;
+
+// Score text in [-1, 1], where negative values indicate negative sentiment
+// and values near zero are neutral.
+pub fn sentiment_score(text: &str) -> f64 {
+    let lower = text.to_lowercase();
+    let mut total: f64 = 0.0;
+    let mut negated_for = 0;
+    for word in lower
+        .split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|x| !x.is_empty())
+    {
+        if in_lexicon(NEGATIONS, word) || word.ends_with("n't") {
+            negated_for = NEGATION_WINDOW;
+            continue;
+        }
+        let polarity = if in_lexicon(POSITIVE, word) {
+            1.0
+        } else if in_lexicon(NEGATIVE, word) {
+            -1.0
+        } else {
+            0.0
+        };
+        total += if negated_for > 0 { -polarity } else { polarity };
+        negated_for = negated_for.saturating_sub(1);
+    }
+    // Squash the raw sum into (-1, 1) so long reviews don't dominate.
+    total / (total * total + 15.0).sqrt()
+}
+
+fn in_lexicon(lexicon: &str, word: &str) -> bool {
+    lexicon.split_whitespace().any(|x| x == word)
+}
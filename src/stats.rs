@@ -0,0 +1,226 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use super::calendar::{format_month, month_of, previous_month};
+use super::client::Review;
+
+const SECONDS_PER_YEAR: f64 = 365.2425 * 86400.0;
+
+// Reviews from this many most recent days count towards the recent average.
+const RECENT_DAYS: f64 = 90.0;
+
+#[derive(Serialize)]
+pub struct MonthCount {
+    pub month: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct ReviewSummary {
+    pub total_reviews: usize,
+    pub truncated: bool,
+
+    // Review counts for the last twelve months (including the current one),
+    // oldest first.
+    pub reviews_per_month: Vec<MonthCount>,
+
+    // The slope of a linear fit of rating against time, in stars per year.
+    pub rating_trend_per_year: Option<f64>,
+
+    pub all_time_average: Option<f64>,
+    pub recent_average: Option<f64>,
+}
+
+impl ReviewSummary {
+    pub fn new(reviews: &[Review], truncated: bool, now: f64) -> Self {
+        let mut month_counts: HashMap<(i64, u32), usize> = HashMap::new();
+        for review in reviews {
+            *month_counts.entry(month_of(review.timestamp)).or_default() += 1;
+        }
+        let mut months = vec![month_of(now)];
+        while months.len() < 12 {
+            months.push(previous_month(*months.last().unwrap()));
+        }
+        let reviews_per_month = months
+            .into_iter()
+            .rev()
+            .map(|month| MonthCount {
+                month: format_month(month),
+                count: month_counts.get(&month).copied().unwrap_or_default(),
+            })
+            .collect();
+
+        let recent_cutoff = now - RECENT_DAYS * 86400.0;
+        ReviewSummary {
+            total_reviews: reviews.len(),
+            truncated,
+            reviews_per_month,
+            rating_trend_per_year: rating_trend(reviews),
+            all_time_average: average_rating(reviews.iter()),
+            recent_average: average_rating(reviews.iter().filter(|x| x.timestamp >= recent_cutoff)),
+        }
+    }
+}
+
+fn average_rating<'a, I: Iterator<Item = &'a Review>>(reviews: I) -> Option<f64> {
+    let (sum, count) = reviews.fold((0.0, 0), |(sum, count), x| (sum + x.rating, count + 1));
+    if count == 0 {
+        None
+    } else {
+        Some(sum / count as f64)
+    }
+}
+
+// Least-squares slope of rating with respect to time, in stars per year.
+fn rating_trend(reviews: &[Review]) -> Option<f64> {
+    if reviews.len() < 2 {
+        return None;
+    }
+    let n = reviews.len() as f64;
+    let mean_t = reviews.iter().map(|x| x.timestamp).sum::<f64>() / n;
+    let mean_r = reviews.iter().map(|x| x.rating).sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for review in reviews {
+        let dt = (review.timestamp - mean_t) / SECONDS_PER_YEAR;
+        cov += dt * (review.rating - mean_r);
+        var += dt * dt;
+    }
+    if var == 0.0 {
+        None
+    } else {
+        Some(cov / var)
+    }
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    Month,
+    Year,
+}
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "month" => Ok(Interval::Month),
+            "year" => Ok(Interval::Year),
+            _ => Err(format!("unknown interval: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DistributionBucket {
+    pub bucket: String,
+
+    // The number of 1 through 5 star reviews in the bucket.
+    pub counts: [u64; 5],
+}
+
+#[derive(Serialize)]
+pub struct RatingDistribution {
+    pub interval: Interval,
+    pub total_reviews: usize,
+    pub truncated: bool,
+
+    // Buckets which contain at least one review, oldest first.
+    pub buckets: Vec<DistributionBucket>,
+}
+
+impl RatingDistribution {
+    pub fn new(reviews: &[Review], truncated: bool, interval: Interval) -> Self {
+        let mut buckets: BTreeMap<(i64, u32), [u64; 5]> = BTreeMap::new();
+        for review in reviews {
+            let (year, month) = month_of(review.timestamp);
+            let key = match interval {
+                Interval::Month => (year, month),
+                Interval::Year => (year, 0),
+            };
+            // Ratings rescaled from other scales may be fractional.
+            let stars = review.rating.round().clamp(1.0, 5.0) as usize;
+            buckets.entry(key).or_default()[stars - 1] += 1;
+        }
+        RatingDistribution {
+            interval,
+            total_reviews: reviews.len(),
+            truncated,
+            buckets: buckets
+                .into_iter()
+                .map(|((year, month), counts)| DistributionBucket {
+                    bucket: match interval {
+                        Interval::Month => format_month((year, month)),
+                        Interval::Year => format!("{:04}", year),
+                    },
+                    counts,
+                })
+                .collect(),
+        }
+    }
+}
+
+// Thins a stream of unknown length down to at most `size` items, spread evenly
+// through the stream. Only O(size) items are kept in memory at once: every
+// stride-th item is kept, and the stride doubles whenever the buffer fills up.
+pub struct EvenSampler<T> {
+    size: usize,
+    stride: usize,
+    seen: usize,
+    kept: Vec<T>,
+}
+
+impl<T> EvenSampler<T> {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0, "sample size must be positive");
+        Self {
+            size,
+            stride: 1,
+            seen: 0,
+            kept: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.seen % self.stride == 0 {
+            self.kept.push(item);
+            if self.kept.len() >= 2 * self.size {
+                self.kept = std::mem::take(&mut self.kept)
+                    .into_iter()
+                    .step_by(2)
+                    .collect();
+                self.stride *= 2;
+            }
+        }
+        self.seen += 1;
+    }
+
+    pub fn finish(self) -> Vec<T> {
+        let len = self.kept.len();
+        if len <= self.size {
+            return self.kept;
+        }
+        // Pick evenly spaced indices from what's left, always starting with
+        // the first item.
+        let mut next_index = 0;
+        let mut count = 0;
+        let size = self.size;
+        self.kept
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, x)| {
+                if count < size && i == next_index {
+                    count += 1;
+                    next_index = count * len / size;
+                    Some(x)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}